@@ -9,9 +9,8 @@ fn main() -> Result<()> {
     let out_dir = env::var("OUT_DIR")?;
     let mut copy_options = CopyOptions::new();
     copy_options.overwrite = true;
-    let mut paths_to_copy = Vec::new();
-    paths_to_copy.push("res/");
-    
+    let paths_to_copy = vec!["res/"];
+
     copy_items(&paths_to_copy, out_dir, &copy_options)?;
 
     Ok(())
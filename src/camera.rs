@@ -29,13 +29,13 @@ impl Camera {
         zfar: f32,
     ) -> Self { 
         Camera {
-            eye: eye,
-            target: target,
-            up: up,
-            aspect: aspect,
-            fovy: fovy,
-            znear: znear,
-            zfar: zfar,
+            eye,
+            target,
+            up,
+            aspect,
+            fovy,
+            znear,
+            zfar,
         }
     }
 
@@ -0,0 +1,150 @@
+// runs a simulation without a window, for CI and cluster jobs
+
+use anyhow::*;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use nbody_3d_v2::simulation::{Diagnostics, Recorder, Simulation};
+
+// exit status when the run completed but the physics didn't hold up
+pub const EXIT_DIVERGED: i32 = 2;
+
+pub struct Options {
+    pub steps: u64,
+    pub output: PathBuf,
+    /// ticks between rows of `diagnostics.csv`
+    pub diagnostics_every: u64,
+    /// ticks between checkpoints written to `snapshot-<tick>.toml`, never if 0
+    pub snapshot_every: u64,
+    /// fail the run once |energy drift| exceeds this
+    pub max_energy_drift: Option<f64>,
+    /// trajectory file, CSV if it ends in `.csv` and binary otherwise
+    pub record: Option<PathBuf>,
+    /// ticks between recorded frames
+    pub record_every: u64,
+}
+
+impl Options {
+    pub fn from_args() -> Result<Self> {
+        fn parse<T: std::str::FromStr>(flag: &str) -> Result<Option<T>>
+        where
+            T::Err: std::error::Error + Send + Sync + 'static,
+        {
+            crate::argument(flag)
+                .map(|value| value.parse().with_context(|| format!("invalid value {:?} for {}", value, flag)))
+                .transpose()
+        }
+
+        Ok(Options {
+            steps: parse("--steps")?.ok_or_else(|| anyhow!("--headless needs --steps <n>"))?,
+            output: parse("--output")?.unwrap_or_else(|| PathBuf::from("output")),
+            diagnostics_every: parse("--diagnostics-every")?.unwrap_or(100).max(1),
+            snapshot_every: parse("--snapshot-every")?.unwrap_or(0),
+            max_energy_drift: parse("--max-energy-drift")?,
+            record: parse("--record")?,
+            record_every: parse("--record-every")?.unwrap_or(1),
+        })
+    }
+}
+
+fn write_diagnostics(out: &mut impl Write, ticks: u64, diagnostics: &Diagnostics) -> Result<()> {
+    writeln!(
+        out,
+        "{},{:e},{:e},{:e},{:e},{:e},{:e},{:e}",
+        ticks,
+        diagnostics.time,
+        diagnostics.kinetic_energy,
+        diagnostics.potential_energy,
+        diagnostics.total_energy(),
+        diagnostics.energy_drift,
+        diagnostics.linear_momentum_drift,
+        diagnostics.angular_momentum_drift,
+    )?;
+    Ok(())
+}
+
+fn snapshot(simulation: &Simulation, output: &Path, ticks: u64) -> Result<()> {
+    simulation.save(output.join(format!("snapshot-{:08}.toml", ticks)))
+}
+
+/// Ticks `simulation` for `options.steps` steps, writing diagnostics and snapshots into `options.output`.
+///
+/// Returns the process exit status: 0 if the run finished cleanly, `EXIT_DIVERGED` if the state
+/// went non-finite or drifted past `max_energy_drift`. I/O problems are returned as errors.
+pub fn run(mut simulation: Simulation, options: &Options) -> Result<i32> {
+    std::fs::create_dir_all(&options.output)
+        .with_context(|| format!("could not create output directory {:?}", options.output))?;
+
+    let path = options.output.join("diagnostics.csv");
+    let file = std::fs::File::create(&path).with_context(|| format!("could not create {:?}", path))?;
+    let mut diagnostics_out = std::io::BufWriter::new(file);
+    writeln!(diagnostics_out, "tick,time,kinetic_energy,potential_energy,total_energy,energy_drift,linear_momentum_drift,angular_momentum_drift")?;
+    write_diagnostics(&mut diagnostics_out, 0, &simulation.diagnostics())?;
+
+    if options.snapshot_every > 0 {
+        snapshot(&simulation, &options.output, 0)?;
+    }
+
+    let mut recorder = match &options.record {
+        Some(path) => Some(Recorder::create(path, options.record_every)?),
+        None => None,
+    };
+
+    if let Some(recorder) = &mut recorder {
+        recorder.write_frame(&simulation)?;
+    }
+
+    let mut status = 0;
+
+    for tick in 1..=options.steps {
+        simulation.tick();
+
+        for merge in simulation.take_merge_events() {
+            log::info!("t = {:.1}: {:?} absorbed {:?}", merge.time, merge.survivor, merge.absorbed);
+        }
+
+        if let Some(recorder) = &mut recorder {
+            recorder.record(&simulation)?;
+        }
+
+        if options.snapshot_every > 0 && tick.is_multiple_of(options.snapshot_every) {
+            snapshot(&simulation, &options.output, tick)?;
+        }
+
+        if !tick.is_multiple_of(options.diagnostics_every) && tick != options.steps {
+            continue;
+        }
+
+        let diagnostics = simulation.diagnostics();
+        write_diagnostics(&mut diagnostics_out, tick, &diagnostics)?;
+
+        if !diagnostics.total_energy().is_finite() {
+            log::error!("t = {:.1}: state is no longer finite, stopping after {} steps", diagnostics.time, tick);
+            status = EXIT_DIVERGED;
+            break;
+        }
+
+        if let Some(max) = options.max_energy_drift {
+            if diagnostics.energy_drift.abs() > max {
+                log::error!(
+                    "t = {:.1}: energy drift {:e} exceeds {:e}, stopping after {} steps",
+                    diagnostics.time,
+                    diagnostics.energy_drift,
+                    max,
+                    tick,
+                );
+                status = EXIT_DIVERGED;
+                break;
+            }
+        }
+    }
+
+    diagnostics_out.flush()?;
+    if let Some(recorder) = &mut recorder {
+        recorder.flush()?;
+    }
+    simulation.save(options.output.join("final.toml"))?;
+    log::info!("finished at t = {:.1}, results in {:?}", simulation.time(), options.output);
+
+    Ok(status)
+}
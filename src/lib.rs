@@ -0,0 +1,15 @@
+//! Newtonian gravity for n massive bodies.
+//!
+//! `simulation` is the physics and has no GPU or windowing dependencies. The wgpu renderer used by
+//! the viewer binary lives in `render`, behind the `render` feature.
+
+pub mod simulation;
+
+#[cfg(feature = "render")]
+mod camera;
+#[cfg(feature = "render")]
+mod model;
+#[cfg(feature = "render")]
+pub mod render;
+#[cfg(feature = "render")]
+mod texture;
@@ -1,131 +1,74 @@
+use nbody_3d_v2::simulation;
 
-use futures::executor::block_on;
-use winit::{
-    dpi::{PhysicalPosition, PhysicalSize},
-    event::*,
-    event_loop::{ControlFlow, EventLoop},
-    window::WindowBuilder,
-};
+mod headless;
+#[cfg(feature = "render")]
+mod replay;
+#[cfg(feature = "render")]
+mod viewer;
 
-mod camera;
-mod model;
-mod render;
-mod simulation;
-mod texture;
+// the value following `flag` on the command line, if any
+fn argument(flag: &str) -> Option<String> {
+    let mut args = std::env::args().skip(1);
 
-fn window_to_view_space(window_size: PhysicalSize<u32>, window_position: PhysicalPosition<f64>) -> cgmath::Vector2<f64> {
-    cgmath::Vector2 {
-        x: window_position.x / window_size.width as f64,
-        y: window_position.y / window_size.height as f64,
+    while let Some(arg) = args.next() {
+        if arg == flag {
+            return args.next();
+        }
     }
-}
-
-fn main() {
-    env_logger::init();
-    let event_loop = EventLoop::new();
-    let window = WindowBuilder::new().build(&event_loop).unwrap();
-    let mut simulation = simulation::Simulation::new();
-    let mut running = true;
-    let mut last_cursor_position: Option<PhysicalPosition<f64>> = None;
-    let mut shift_down = false;
-    let mut ctrl_down = false;
-    
-    let mut render_state = block_on(render::State::new(&window));
-
-    event_loop.run(move |event, _, control_flow| match event {
-        Event::WindowEvent {
-            ref event,
-            window_id,
-        } if window_id == window.id() => if !render_state.input(event) {
-            match event {
-                WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
-
-                WindowEvent::KeyboardInput { input, .. } => match input {
-                    KeyboardInput {
-                        state: ElementState::Pressed,
-                        virtual_keycode: Some(VirtualKeyCode::Escape),
-                        ..
-                    } => *control_flow = ControlFlow::Exit,
-                    KeyboardInput { 
-                        state: ElementState::Pressed, 
-                        virtual_keycode: Some(VirtualKeyCode::Space),
-                        ..
-                    } => running = !running,
-                    KeyboardInput { 
-                        state, 
-                        virtual_keycode: Some(VirtualKeyCode::LShift),
-                        ..
-                    } => shift_down = *state == ElementState::Pressed,
-                    KeyboardInput { 
-                        state, 
-                        virtual_keycode: Some(VirtualKeyCode::LControl),
-                        ..
-                    } => ctrl_down = *state == ElementState::Pressed,
-                    _ => {}
-                }
-
-                WindowEvent::CursorMoved { position, .. } => {
-                    last_cursor_position = Some(*position);
-                }
-
-                WindowEvent::CursorLeft { .. } => {
-                    last_cursor_position = None;
-                }
-
-                WindowEvent::MouseInput { state, button, .. } => {
-                    if *state == ElementState::Released && *button == MouseButton::Left {
-                        if let Some(position) = last_cursor_position {
-                            let view_position = window_to_view_space(window.inner_size(), position);
-                            let barycentric_position = render_state.view_to_world_space(view_position);
-
-                            let mass = if ctrl_down { 
-                                simulation::BodyMass::Large 
-                            } else if shift_down {
-                                simulation::BodyMass::Medium
-                            } else {
-                                simulation::BodyMass::Small
-                            };
 
-                            simulation.add_body_at_position(barycentric_position, mass);
-                        }
-                    }
-                }
+    None
+}
 
-                WindowEvent::Resized(physical_size) => {
-                    render_state.resize(Some(*physical_size));
-                }
+fn exit_on_error<T>(result: anyhow::Result<T>) -> T {
+    result.unwrap_or_else(|e| {
+        eprintln!("{:?}", e);
+        std::process::exit(1);
+    })
+}
 
-                WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
-                    render_state.resize(Some(**new_inner_size));
-                }
-                _ => {}
-            } 
-        }
+fn default_simulation() -> simulation::Simulation {
+    let mut simulation = simulation::Simulation::new();
+    // shrink the step during close passes so freshly added bodies aren't flung out of the system
+    simulation.set_timestep(simulation::Timestep::Adaptive { eta: 0.05, min: 0.001, max: 1.0 });
+    // keeps the frame rate up once a few hundred bodies have been clicked in
+    simulation.set_force_solver(simulation::ForceSolver::BarnesHut { theta: 0.5 });
+    simulation.set_softening(simulation::Softening::Plummer(0.05));
+    simulation.set_collision_mode(simulation::CollisionMode::Merge).expect("the default units give bodies a size");
+    simulation
+}
 
-        Event::RedrawRequested(_) => {
-            if running {
-                simulation.tick();
-            }
-                
-            let barycenter = simulation.barycenter();
-            render_state.update_light((barycenter.x as f32, barycenter.y as f32, barycenter.z as f32).into());
-            render_state.update_camera((barycenter.x as f32, barycenter.y as f32, barycenter.z as f32).into());
+fn main() {
+    env_logger::init();
 
-            let instances = simulation.instances();
-            render_state.update_instances(instances);
+    #[cfg(feature = "render")]
+    if let Some(path) = argument("--replay") {
+        viewer::run(viewer::Source::Replay(exit_on_error(replay::Replay::load(&path))));
+    }
 
-            match render_state.render() {
-                Ok(_) => {}
-                Err(wgpu::SwapChainError::Lost) => render_state.resize(None),
-                Err(wgpu::SwapChainError::OutOfMemory) => *control_flow = ControlFlow::Exit,
-                Err(e) => eprintln!("{:?}", e),
-            }
-        }
+    let simulation = if let Some(path) = argument("--checkpoint") {
+        exit_on_error(simulation::Simulation::load(&path))
+    } else if let Some(name) = argument("--preset") {
+        simulation::Simulation::from_preset(exit_on_error(name.parse()))
+    } else if let Some(path) = argument("--scenario") {
+        exit_on_error(simulation::Scenario::load(&path).and_then(|scenario| simulation::Simulation::from_scenario(&scenario)))
+    } else {
+        default_simulation()
+    };
+    #[cfg(feature = "parallel")]
+    let simulation = {
+        let mut simulation = simulation;
+        simulation.set_parallelism(simulation::Parallelism::Parallel { deterministic: true });
+        simulation
+    };
+
+    if std::env::args().any(|arg| arg == "--headless") {
+        let options = exit_on_error(headless::Options::from_args());
+        std::process::exit(exit_on_error(headless::run(simulation, &options)));
+    }
 
-        Event::MainEventsCleared => {
-            window.request_redraw()
-        }
+    #[cfg(feature = "render")]
+    viewer::run(viewer::Source::Live(Box::new(simulation)));
 
-        _ => {}
-    });
+    #[cfg(not(feature = "render"))]
+    exit_on_error(Err(anyhow::anyhow!("built without the render feature, only --headless runs are available")))
 }
@@ -44,10 +44,12 @@ impl Vertex for ModelVertex {
 }
 
 pub struct Mesh {
+    #[allow(dead_code)]
     pub name: String,
     pub vertex_buffer: wgpu::Buffer,
     pub index_buffer: wgpu::Buffer,
     pub num_elements: u32,
+    #[allow(dead_code)]
     pub material: usize,
 }
 
@@ -120,6 +122,7 @@ pub trait DrawModel<'a, 'b>
 where
     'b: 'a,
 {
+    #[allow(dead_code)]
     fn draw_mesh(
         &mut self, 
         mesh: &'b Mesh,
@@ -134,6 +137,7 @@ where
         light: &'b wgpu::BindGroup,
     );
 
+    #[allow(dead_code)]
     fn draw_model(
         &mut self,
         model: &'b Model,
@@ -171,8 +175,8 @@ where
     ) {
         self.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
         self.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
-        self.set_bind_group(0, &uniforms, &[]);
-        self.set_bind_group(1, &light, &[]);
+        self.set_bind_group(0, uniforms, &[]);
+        self.set_bind_group(1, light, &[]);
         self.draw_indexed(0..mesh.num_elements, 0, instances);
     }
 
@@ -202,6 +206,7 @@ pub trait DrawLight<'a, 'b>
 where
     'b: 'a,
 {
+    #[allow(dead_code)]
     fn draw_light_mesh(
         &mut self,
         mesh: &'b Mesh,
@@ -0,0 +1,45 @@
+// Vertex shader
+
+[[block]]
+struct Uniforms {
+    view_pos: vec4<f32>;
+    view_proj: mat4x4<f32>;
+};
+[[group(0), binding(0)]]
+var<uniform> uniforms: Uniforms;
+
+struct VertexInput {
+    [[location(0)]] position: vec3<f32>;
+};
+
+struct InstanceInput {
+    // xyz is the particle's position, w its size
+    [[location(5)]] position_size: vec4<f32>;
+    [[location(6)]] color: vec4<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] color: vec4<f32>;
+};
+
+[[stage(vertex)]]
+fn main(
+    model: VertexInput,
+    instance: InstanceInput,
+) -> VertexOutput {
+    var out: VertexOutput;
+
+    let world_position = instance.position_size.xyz + model.position * instance.position_size.w;
+    out.clip_position = uniforms.view_proj * vec4<f32>(world_position, 1.0);
+    out.color = instance.color;
+    return out;
+}
+
+// Fragment shader
+
+// particles are too small to be worth lighting
+[[stage(fragment)]]
+fn main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    return in.color;
+}
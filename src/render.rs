@@ -105,6 +105,8 @@ impl InstanceRaw {
 }
 
 pub struct Instance {
+    /// the body this instance draws, so picking can map back into the simulation
+    pub id: crate::simulation::BodyId,
     pub position: cgmath::Vector3<f32>,
     pub rotation: cgmath::Quaternion<f32>,
     pub color: [f32; 4],
@@ -121,6 +123,86 @@ impl Instance {
     }
 }
 
+/// A massless test particle, drawn as a small unlit octahedron.
+pub struct Particle {
+    pub position: cgmath::Vector3<f32>,
+    pub color: [f32; 4],
+    pub size: f32,
+}
+
+impl Particle {
+    fn to_raw(&self) -> ParticleRaw {
+        ParticleRaw {
+            position_size: [self.position.x, self.position.y, self.position.z, self.size],
+            color: self.color,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ParticleRaw {
+    position_size: [f32; 4],
+    color: [f32; 4],
+}
+
+impl ParticleRaw {
+    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        use std::mem;
+        wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<ParticleRaw>() as wgpu::BufferAddress,
+            step_mode: wgpu::InputStepMode::Instance,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 5,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
+                    shader_location: 6,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+            ],
+        }
+    }
+}
+
+// far fewer triangles than the sphere model, since there may be many thousands of particles
+const PARTICLE_VERTICES: [[f32; 3]; 6] = [
+    [1.0, 0.0, 0.0],
+    [-1.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0],
+    [0.0, -1.0, 0.0],
+    [0.0, 0.0, 1.0],
+    [0.0, 0.0, -1.0],
+];
+
+const PARTICLE_INDICES: [u16; 24] = [
+    0, 2, 4,
+    2, 1, 4,
+    1, 3, 4,
+    3, 0, 4,
+    2, 0, 5,
+    1, 2, 5,
+    3, 1, 5,
+    0, 3, 5,
+];
+
+fn particle_vertex_desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+    wgpu::VertexBufferLayout {
+        array_stride: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+        step_mode: wgpu::InputStepMode::Vertex,
+        attributes: &[
+            wgpu::VertexAttribute {
+                offset: 0,
+                shader_location: 0,
+                format: wgpu::VertexFormat::Float32x3,
+            },
+        ],
+    }
+}
+
 fn create_render_pipeline(
     device: &wgpu::Device,
     layout: &wgpu::PipelineLayout,
@@ -133,7 +215,7 @@ fn create_render_pipeline(
 
     device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
         label: Some("Render Pipeline"),
-        layout: Some(&layout),
+        layout: Some(layout),
         vertex: wgpu::VertexState {
             module: &shader,
             entry_point: "main",
@@ -190,6 +272,11 @@ pub struct State {
     obj_model: model::Model,
     instances: Vec<Instance>,
     instance_buffer: wgpu::Buffer,
+    particle_render_pipeline: wgpu::RenderPipeline,
+    particle_vertex_buffer: wgpu::Buffer,
+    particle_index_buffer: wgpu::Buffer,
+    particles: Vec<Particle>,
+    particle_buffer: wgpu::Buffer,
     camera: camera::Camera,
     camera_controller: camera::CameraController,
     uniforms: Uniforms,
@@ -241,6 +328,27 @@ impl State {
             usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
         });
 
+        let particles = Vec::new();
+
+        let particle_data = particles.iter().map(Particle::to_raw).collect::<Vec<_>>();
+        let particle_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Particle Buffer"),
+            contents: bytemuck::cast_slice(&particle_data),
+            usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
+        });
+
+        let particle_vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Particle Vertex Buffer"),
+            contents: bytemuck::cast_slice(&PARTICLE_VERTICES),
+            usage: wgpu::BufferUsage::VERTEX,
+        });
+
+        let particle_index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Particle Index Buffer"),
+            contents: bytemuck::cast_slice(&PARTICLE_INDICES),
+            usage: wgpu::BufferUsage::INDEX,
+        });
+
         let camera = camera::Camera::new(
             // position the camera one unit up and 2 units back
             // z is out of the screen
@@ -386,6 +494,27 @@ impl State {
                 shader,
             )
         };
+
+        let particle_render_pipeline = {
+            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Particle Pipeline Layout"),
+                bind_group_layouts: &[&uniform_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+            let shader = wgpu::ShaderModuleDescriptor {
+                label: Some("Particle Shader"),
+                flags: wgpu::ShaderFlags::all(),
+                source: wgpu::ShaderSource::Wgsl(include_str!("particle.wgsl").into()),
+            };
+            create_render_pipeline(
+                &device,
+                &layout,
+                sc_desc.format,
+                Some(texture::Texture::DEPTH_FORMAT),
+                &[particle_vertex_desc(), ParticleRaw::desc()],
+                shader,
+            )
+        };
         
 
         Self {
@@ -397,6 +526,11 @@ impl State {
             size,
             instances,
             instance_buffer,
+            particle_render_pipeline,
+            particle_vertex_buffer,
+            particle_index_buffer,
+            particles,
+            particle_buffer,
             camera,
             camera_controller,
             uniforms,
@@ -458,6 +592,22 @@ impl State {
         };
     }
 
+    pub fn update_particles(&mut self, new_particles: Vec<Particle>) {
+        if new_particles.len() > self.particles.len() {
+            self.particles = new_particles;
+            let particle_data = self.particles.iter().map(Particle::to_raw).collect::<Vec<_>>();
+            self.particle_buffer = self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Particle Buffer"),
+                contents: bytemuck::cast_slice(&particle_data),
+                usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
+            });
+        } else {
+            self.particles = new_particles;
+            let particle_data = self.particles.iter().map(Particle::to_raw).collect::<Vec<_>>();
+            self.queue.write_buffer(&self.particle_buffer, 0, bytemuck::cast_slice(&particle_data));
+        };
+    }
+
     pub fn render(&mut self) -> Result<(), wgpu::SwapChainError> {
         let frame = self
             .swap_chain
@@ -516,6 +666,15 @@ impl State {
                 &self.uniform_bind_group,
                 &self.light_bind_group,
             );
+
+            if !self.particles.is_empty() {
+                render_pass.set_pipeline(&self.particle_render_pipeline);
+                render_pass.set_vertex_buffer(0, self.particle_vertex_buffer.slice(..));
+                render_pass.set_vertex_buffer(1, self.particle_buffer.slice(..));
+                render_pass.set_index_buffer(self.particle_index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+                render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+                render_pass.draw_indexed(0..PARTICLE_INDICES.len() as u32, 0, 0..self.particles.len() as u32);
+            }
         }
 
         self.queue.submit(std::iter::once(encoder.finish()));
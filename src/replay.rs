@@ -0,0 +1,68 @@
+// playback position within a recorded trajectory, for watching runs without recomputing them
+
+use anyhow::*;
+use std::path::Path;
+
+use nbody_3d_v2::simulation::{Frame, TrajectoryReader};
+
+// playback speeds are kept between 1/64 and 64 recorded frames per redraw
+const MIN_SPEED: f64 = 1.0 / 64.0;
+const MAX_SPEED: f64 = 64.0;
+
+pub struct Replay {
+    frames: Vec<Frame>,
+    // fractional so that slow playback lingers on each frame
+    position: f64,
+    /// frames advanced per redraw, negative when playing backwards
+    speed: f64,
+}
+
+impl Replay {
+    pub fn new(frames: Vec<Frame>) -> Result<Self> {
+        ensure!(!frames.is_empty(), "recording has no frames");
+        Ok(Replay { frames, position: 0.0, speed: 1.0 })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let frames = TrajectoryReader::open(path.as_ref())?
+            .collect::<Result<Vec<_>>>()
+            .with_context(|| format!("invalid recording {:?}", path.as_ref()))?;
+
+        Self::new(frames)
+    }
+
+    fn last(&self) -> f64 {
+        (self.frames.len() - 1) as f64
+    }
+
+    pub fn frame(&self) -> &Frame {
+        &self.frames[self.position.round() as usize]
+    }
+
+    /// Moves on by one redraw's worth of frames, stopping at either end.
+    pub fn advance(&mut self) {
+        self.position = (self.position + self.speed).clamp(0.0, self.last());
+    }
+
+    /// Moves `frames` whole frames forwards, or backwards if negative.
+    pub fn step(&mut self, frames: i64) {
+        self.position = (self.position.round() + frames as f64).clamp(0.0, self.last());
+    }
+
+    /// Jumps to `fraction` of the way through the recording.
+    pub fn seek(&mut self, fraction: f64) {
+        self.position = (fraction.clamp(0.0, 1.0) * self.last()).round();
+    }
+
+    pub fn seek_end(&mut self) {
+        self.position = self.last();
+    }
+
+    pub fn set_speed(&mut self, speed: f64) {
+        self.speed = speed.signum() * speed.abs().clamp(MIN_SPEED, MAX_SPEED);
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+}
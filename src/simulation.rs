@@ -1,23 +1,110 @@
 use cgmath::prelude::*;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
 
+#[cfg(feature = "render")]
 use crate::render::Instance;
 
-const G: f64 = 0.00000001;
+mod checkpoint;
+mod ephemeris;
+mod generators;
+mod kepler;
+mod octree;
+mod orbit;
+mod particles;
+mod presets;
+mod recording;
+mod scalar;
+mod scenario;
+mod summation;
+mod units;
+
+pub use ephemeris::{Ephemeris, EphemerisBody};
+pub use generators::{disk_galaxy, king_model, place_bodies, plummer_sphere, GalaxyModel};
+pub use kepler::{propagate_kepler, solve_kepler, true_anomaly_from_mean};
+pub use orbit::{OrbitalElements, Primary};
+pub use particles::TestParticle;
+pub use presets::Preset;
+pub use recording::{Frame, RecordedBody, Recorder, RecordingFormat, TrajectoryReader};
+pub use scalar::{DoubleDouble, Scalar};
+pub use scenario::Scenario;
+pub use summation::Summation;
+pub use units::{Conversion, UnitSystem, G_SI};
+
+/// Gravitational constant of the default, dimensionless units, in which the viewer's bodies weigh
+/// millions.
+pub const G: f64 = 0.00000001;
 
 const BODY_COLOR: [f32; 4] = [0.0, 0.2, 0.60, 1.0];
 
-#[derive(Clone, Debug)]
-struct Body {
-    position: cgmath::Vector3<f64>,
-    velocity: cgmath::Vector3<f64>,
-    mass: f64,
+/// Identifies a body for as long as it exists in its simulation. Ids are never reused.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+pub struct BodyId(u64);
+
+/// A massive body, with its state in `f64` unless another `Scalar` is given.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Body<S = f64> {
+    id: BodyId,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub position: cgmath::Vector3<S>,
+    pub velocity: cgmath::Vector3<S>,
+    pub mass: S,
+    pub color: [f32; 4],
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    compensation: Option<summation::Compensation<S>>,
 }
 
-impl PartialEq for Body {
-    // for now, just check that they don't have the exact same position.
-    // this would be a singularity anyway (infinite Fg).
+impl<S: Scalar> Body<S> {
+    /// A new, unnamed body. Its id is assigned when it is added to a simulation.
+    pub fn new(position: cgmath::Vector3<S>, velocity: cgmath::Vector3<S>, mass: S) -> Self {
+        Body {
+            id: BodyId(0),
+            name: None,
+            position,
+            velocity,
+            mass,
+            color: BODY_COLOR,
+            compensation: None,
+        }
+    }
+
+    pub fn id(&self) -> BodyId {
+        self.id
+    }
+
+    /// Drawn size of the body, and its collision radius in dimensionless units. See
+    /// `UnitSystem::collision_radius` for the others.
+    pub fn radius(&self) -> f64 {
+        radius_for_mass(self.mass.into_f64())
+    }
+
+    /// The same body in another precision, going through `f64`, e.g. to start otherwise identical
+    /// simulations in `f32`, `f64` and `DoubleDouble` and watch their trajectories diverge.
+    pub fn cast<T: Scalar>(&self) -> Body<T> {
+        let cast = |x: S| T::from_f64(x.into_f64());
+
+        Body {
+            id: self.id,
+            name: self.name.clone(),
+            position: self.position.map(cast),
+            velocity: self.velocity.map(cast),
+            mass: cast(self.mass),
+            color: self.color,
+            compensation: None,
+        }
+    }
+}
+
+// drawn size of a body, so that masses spanning many orders of magnitude stay visible
+pub(crate) fn radius_for_mass(mass: f64) -> f64 {
+    (mass.log10() / 7.0).max(0.0)
+}
+
+impl<S> PartialEq for Body<S> {
+    // the same body, not merely one in the same state
     fn eq(&self, other: &Self) -> bool {
-        self.position == other.position
+        self.id == other.id
     }
 }
 
@@ -26,68 +113,884 @@ enum SimulationBuffer {
     Buffer1,
 }
 
+/// How heavy a body added with `add_body_at_position` is. These suit the default units' system; in
+/// any other, they are a millionth, a ten-thousandth and a hundredth of the mass already there.
 pub enum BodyMass {
     Small,
     Medium,
     Large,
 }
 
-pub struct Simulation {
-    buffer0: Vec<Body>,
-    buffer1: Vec<Body>,
+/// Which way round a body added with `add_body_at_position` orbits, looking down the z axis.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OrbitDirection {
+    /// counter-clockwise
+    Prograde,
+    Retrograde,
+}
+
+/// Advances every body by one timestep, reading from `current` and writing into `next`.
+/// `accelerations` evaluates the gravitational acceleration on each body of a given state.
+pub trait Integrator<S: Scalar = f64> {
+    #[allow(clippy::type_complexity)]
+    fn step(
+        &self,
+        current: &[Body<S>],
+        next: &mut [Body<S>],
+        dt: S,
+        accelerations: &dyn Fn(&[Body<S>]) -> Vec<cgmath::Vector3<S>>,
+    );
+
+    /// Which built-in integrator this is, if any. Only built-in integrators can be checkpointed.
+    fn kind(&self) -> Option<IntegratorKind> {
+        None
+    }
+}
+
+/// Semi-implicit (symplectic) Euler: kick with the current acceleration, then drift with the new velocity.
+/// First order, but cheap.
+pub struct Euler;
+
+impl<S: Scalar> Integrator<S> for Euler {
+    fn kind(&self) -> Option<IntegratorKind> {
+        Some(IntegratorKind::Euler)
+    }
+
+    fn step(
+        &self,
+        current: &[Body<S>],
+        next: &mut [Body<S>],
+        dt: S,
+        accelerations: &dyn Fn(&[Body<S>]) -> Vec<cgmath::Vector3<S>>,
+    ) {
+        let acceleration = accelerations(current);
+
+        for ((current, next), a) in current.iter().zip(next.iter_mut()).zip(acceleration) {
+            next.begin_step(current);
+            next.kick(a * dt);
+            next.drift(next.velocity * dt);
+        }
+    }
+}
+
+/// Kick-drift-kick leapfrog. Second order and symplectic, so energy errors stay bounded over long runs.
+pub struct Leapfrog;
+
+impl<S: Scalar> Integrator<S> for Leapfrog {
+    fn kind(&self) -> Option<IntegratorKind> {
+        Some(IntegratorKind::Leapfrog)
+    }
+
+    fn step(
+        &self,
+        current: &[Body<S>],
+        next: &mut [Body<S>],
+        dt: S,
+        accelerations: &dyn Fn(&[Body<S>]) -> Vec<cgmath::Vector3<S>>,
+    ) {
+        let half_dt = S::from_f64(0.5) * dt;
+        let acceleration = accelerations(current);
+
+        // half kick, then full drift, written straight into the next buffer
+        for ((current, next), a) in current.iter().zip(next.iter_mut()).zip(acceleration) {
+            next.begin_step(current);
+            next.kick(a * half_dt);
+            next.drift(next.velocity * dt);
+        }
+
+        // second half kick at the drifted positions
+        let acceleration = accelerations(next);
+        for (next, a) in next.iter_mut().zip(acceleration) {
+            next.kick(a * half_dt);
+        }
+    }
+}
+
+/// Velocity Verlet: full position update from the current velocity and acceleration,
+/// then a velocity update from the average of the old and new accelerations.
+pub struct VelocityVerlet;
+
+impl<S: Scalar> Integrator<S> for VelocityVerlet {
+    fn kind(&self) -> Option<IntegratorKind> {
+        Some(IntegratorKind::VelocityVerlet)
+    }
+
+    fn step(
+        &self,
+        current: &[Body<S>],
+        next: &mut [Body<S>],
+        dt: S,
+        accelerations: &dyn Fn(&[Body<S>]) -> Vec<cgmath::Vector3<S>>,
+    ) {
+        let half = S::from_f64(0.5);
+        let acceleration = accelerations(current);
+
+        for ((current, next), a) in current.iter().zip(next.iter_mut()).zip(acceleration.iter()) {
+            next.begin_step(current);
+            next.drift(current.velocity * dt);
+            next.drift(a * (half * dt * dt));
+        }
+
+        let next_acceleration = accelerations(next);
+        for ((next, a), next_a) in next.iter_mut().zip(acceleration).zip(next_acceleration) {
+            next.kick((a + next_a) * (half * dt));
+        }
+    }
+}
+
+/// Classic fourth-order Runge-Kutta. Not symplectic, but very accurate for short, smooth arcs.
+pub struct Rk4;
+
+impl<S: Scalar> Integrator<S> for Rk4 {
+    fn kind(&self) -> Option<IntegratorKind> {
+        Some(IntegratorKind::Rk4)
+    }
+
+    fn step(
+        &self,
+        current: &[Body<S>],
+        next: &mut [Body<S>],
+        dt: S,
+        accelerations: &dyn Fn(&[Body<S>]) -> Vec<cgmath::Vector3<S>>,
+    ) {
+        let (half_dt, two, sixth_dt) = (S::from_f64(0.5) * dt, S::from_f64(2.0), dt / S::from_f64(6.0));
+
+        // each stage derivative is (velocity, acceleration) for every body
+        let offset_state = |dx: &[cgmath::Vector3<S>], dv: &[cgmath::Vector3<S>], h: S| {
+            current.iter().zip(dx).zip(dv).map(|((body, dx), dv)| {
+                Body::new(body.position + dx * h, body.velocity + dv * h, body.mass)
+            }).collect::<Vec<_>>()
+        };
+
+        let k1_x = current.iter().map(|b| b.velocity).collect::<Vec<_>>();
+        let k1_v = accelerations(current);
+
+        let stage = offset_state(&k1_x, &k1_v, half_dt);
+        let k2_x = stage.iter().map(|b| b.velocity).collect::<Vec<_>>();
+        let k2_v = accelerations(&stage);
+
+        let stage = offset_state(&k2_x, &k2_v, half_dt);
+        let k3_x = stage.iter().map(|b| b.velocity).collect::<Vec<_>>();
+        let k3_v = accelerations(&stage);
+
+        let stage = offset_state(&k3_x, &k3_v, dt);
+        let k4_x = stage.iter().map(|b| b.velocity).collect::<Vec<_>>();
+        let k4_v = accelerations(&stage);
+
+        for (i, (current, next)) in current.iter().zip(next.iter_mut()).enumerate() {
+            next.begin_step(current);
+            next.drift((k1_x[i] + k2_x[i] * two + k3_x[i] * two + k4_x[i]) * sixth_dt);
+            next.kick((k1_v[i] + k2_v[i] * two + k3_v[i] * two + k4_v[i]) * sixth_dt);
+        }
+    }
+}
+
+/// The built-in integrators, by name.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum IntegratorKind {
+    Euler,
+    Leapfrog,
+    VelocityVerlet,
+    Rk4,
+}
+
+impl IntegratorKind {
+    pub fn integrator<S: Scalar>(&self) -> Box<dyn Integrator<S>> {
+        match self {
+            IntegratorKind::Euler => Box::new(Euler),
+            IntegratorKind::Leapfrog => Box::new(Leapfrog),
+            IntegratorKind::VelocityVerlet => Box::new(VelocityVerlet),
+            IntegratorKind::Rk4 => Box::new(Rk4),
+        }
+    }
+}
+
+/// How far each `tick()` advances the simulation clock.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Timestep {
+    /// Every tick advances by exactly `dt`.
+    Fixed(f64),
+    /// Each tick advances by `eta` times the shortest free-fall or encounter timescale
+    /// between any pair of bodies, clamped to `[min, max]`.
+    Adaptive { eta: f64, min: f64, max: f64 },
+}
+
+/// A snapshot of the conserved quantities of a simulation, and how far each has drifted
+/// from its value at t=0 (or since bodies were last added).
+#[derive(Clone, Debug)]
+pub struct Diagnostics {
+    pub time: f64,
+    pub kinetic_energy: f64,
+    pub potential_energy: f64,
+    pub linear_momentum: cgmath::Vector3<f64>,
+    /// angular momentum about the barycenter, in the barycentric frame
+    pub angular_momentum: cgmath::Vector3<f64>,
+    /// (E - E0) / |E0|
+    pub energy_drift: f64,
+    /// |P - P0| / sum(m|v|) at t=0, since the total momentum itself is usually zero
+    pub linear_momentum_drift: f64,
+    /// |L - L0| / |L0|
+    pub angular_momentum_drift: f64,
+}
+
+impl Diagnostics {
+    pub fn total_energy(&self) -> f64 {
+        self.kinetic_energy + self.potential_energy
+    }
+}
+
+// reference values the drifts in `Diagnostics` are measured against
+#[derive(Clone, Copy, serde::Serialize, serde::Deserialize)]
+struct ConservedQuantities<S = f64> {
+    energy: S,
+    linear_momentum: cgmath::Vector3<S>,
+    momentum_scale: S,
+    angular_momentum: cgmath::Vector3<S>,
+}
+
+impl<S: Scalar> ConservedQuantities<S> {
+    fn of(bodies: &[Body<S>], forces: &ForceModel<S>) -> Self {
+        ConservedQuantities {
+            energy: kinetic_energy(bodies) + potential_energy(bodies, forces),
+            linear_momentum: linear_momentum(bodies),
+            momentum_scale: bodies.iter().map(|b| b.mass * b.velocity.magnitude()).sum(),
+            angular_momentum: angular_momentum(bodies),
+        }
+    }
+}
+
+// `difference` relative to `scale`, or absolute if there is nothing to scale against
+fn relative_drift<S: Scalar>(difference: S, scale: S) -> S {
+    if scale != S::zero() {
+        difference / scale.abs()
+    } else {
+        difference
+    }
+}
+
+/// How the gravitational acceleration on each body is evaluated.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ForceSolver {
+    /// Exact O(N^2) summation over every pair of bodies.
+    Direct,
+    /// O(N log N) Barnes-Hut octree. Smaller opening angles `theta` are more accurate and slower.
+    BarnesHut { theta: f64 },
+}
+
+/// Whether force evaluation is spread across threads.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Parallelism {
+    Sequential,
+    /// Each body's acceleration is evaluated on the rayon thread pool. With `deterministic`,
+    /// every body still sums its contributions in index order, so results are bit-identical
+    /// to `Sequential`; without it, direct summation may also split each body's sum across
+    /// threads, and the rounding then depends on scheduling.
+    #[cfg(feature = "parallel")]
+    Parallel { deterministic: bool },
+}
+
+/// Smooths the force law at short range so close encounters don't produce unbounded accelerations.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Softening {
+    /// Pure inverse-square law.
+    None,
+    /// Plummer softening with length epsilon: every point mass acts like a Plummer sphere,
+    /// F = G m1 m2 r / (r^2 + epsilon^2)^(3/2).
+    Plummer(f64),
+    /// Cubic spline kernel with Plummer-equivalent length epsilon. The force is exactly
+    /// Newtonian beyond 2.8 epsilon, and smoothly falls to zero inside it.
+    Spline(f64),
+}
+
+// kernel support radius for spline softening, in units of the Plummer-equivalent length
+const SPLINE_KERNEL_RADIUS: f64 = 2.8;
+
+impl Softening {
+    fn length(&self) -> f64 {
+        match *self {
+            Softening::None => 0.0,
+            Softening::Plummer(epsilon) | Softening::Spline(epsilon) => epsilon,
+        }
+    }
+
+    // the softened equivalent of 1/r^3, so that the acceleration towards a mass m is G m d * force_kernel(|d|^2)
+    fn force_kernel<S: Scalar>(&self, distance2: S) -> S {
+        let c = S::from_f64;
+
+        match *self {
+            Softening::None => {
+                if distance2 > S::zero() {
+                    S::one() / (distance2 * distance2.sqrt())
+                } else {
+                    // coincident points have no direction to pull in
+                    S::zero()
+                }
+            }
+            Softening::Plummer(epsilon) => {
+                let epsilon = c(epsilon);
+                let softened2 = distance2 + epsilon * epsilon;
+                if softened2 > S::zero() {
+                    S::one() / (softened2 * softened2.sqrt())
+                } else {
+                    S::zero()
+                }
+            }
+            Softening::Spline(epsilon) => {
+                let h = c(SPLINE_KERNEL_RADIUS * epsilon);
+                let distance = distance2.sqrt();
+
+                if distance >= h {
+                    Softening::None.force_kernel(distance2)
+                } else {
+                    let u = distance / h;
+                    let h_inv3 = S::one() / (h * h * h);
+
+                    if u < c(0.5) {
+                        h_inv3 * (c(32.0 / 3.0) + u * u * (c(32.0) * u - c(38.4)))
+                    } else {
+                        h_inv3 * (c(64.0 / 3.0) - c(48.0) * u + c(38.4) * u * u - c(32.0 / 3.0) * u * u * u
+                            - S::one() / (c(15.0) * u * u * u))
+                    }
+                }
+            }
+        }
+    }
+
+    // the softened equivalent of 1/r, so that the potential energy of a pair is -G m1 m2 potential_kernel(r)
+    fn potential_kernel<S: Scalar>(&self, distance: S) -> S {
+        let c = S::from_f64;
+
+        match *self {
+            Softening::None => S::one() / distance,
+            Softening::Plummer(epsilon) => S::one() / (distance * distance + c(epsilon) * c(epsilon)).sqrt(),
+            Softening::Spline(epsilon) => {
+                let h = c(SPLINE_KERNEL_RADIUS * epsilon);
+
+                if distance >= h {
+                    S::one() / distance
+                } else {
+                    let u = distance / h;
+
+                    if u < c(0.5) {
+                        (c(2.8) - u * u * (c(16.0 / 3.0) + u * u * (c(6.4) * u - c(9.6)))) / h
+                    } else {
+                        (c(3.2) - S::one() / (c(15.0) * u)
+                            - u * u * (c(32.0 / 3.0) + u * (c(-16.0) + u * (c(9.6) - c(32.0 / 15.0) * u)))) / h
+                    }
+                }
+            }
+        }
+    }
+}
+
+// everything that determines how accelerations are evaluated for a given state
+#[derive(Clone, Copy, Debug)]
+struct ForceModel<S = f64> {
+    gravitational_constant: S,
+    solver: ForceSolver,
+    parallelism: Parallelism,
+    softening: Softening,
+    summation: Summation,
+}
+
+impl<S: Scalar> ForceModel<S> {
+    fn accelerations(&self, bodies: &[Body<S>]) -> Vec<cgmath::Vector3<S>> {
+        match self.solver {
+            ForceSolver::Direct => match self.parallelism {
+                Parallelism::Sequential => {
+                    (0..bodies.len()).map(|i| direct_acceleration(bodies, i, self)).collect()
+                }
+                #[cfg(feature = "parallel")]
+                Parallelism::Parallel { deterministic: true } => {
+                    (0..bodies.len()).into_par_iter().map(|i| direct_acceleration(bodies, i, self)).collect()
+                }
+                #[cfg(feature = "parallel")]
+                Parallelism::Parallel { deterministic: false } => {
+                    bodies.par_iter().enumerate().map(|(i, current)| {
+                        bodies.par_iter()
+                            .enumerate()
+                            .filter(|(j, _)| *j != i)
+                            .map(|(_, b)| point_mass_acceleration(current.position, b.position, b.mass, self))
+                            .reduce(cgmath::Vector3::zero, |a, b| a + b)
+                    }).collect()
+                }
+            },
+            ForceSolver::BarnesHut { theta } => {
+                let tree = octree::Octree::new(bodies);
+
+                match self.parallelism {
+                    Parallelism::Sequential => {
+                        (0..bodies.len()).map(|i| tree.acceleration(i, theta, self)).collect()
+                    }
+                    // tree traversal order is fixed per body, so this is deterministic either way
+                    #[cfg(feature = "parallel")]
+                    Parallelism::Parallel { .. } => {
+                        (0..bodies.len()).into_par_iter().map(|i| tree.acceleration(i, theta, self)).collect()
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// What happens when the physical radii of two bodies overlap.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CollisionMode {
+    /// Bodies pass straight through each other.
+    None,
+    /// Overlapping bodies merge into one, conserving mass and momentum.
+    Merge,
+}
+
+/// Reported whenever two bodies merge into one.
+#[derive(Clone, Debug)]
+pub struct MergeEvent<S = f64> {
+    pub time: f64,
+    /// the more massive of the pair, which carries on as the merged body
+    pub survivor: BodyId,
+    /// the body that no longer exists
+    pub absorbed: BodyId,
+    /// masses of the survivor and the absorbed body before the collision
+    pub masses: (S, S),
+    /// the merged body
+    pub merged: Body<S>,
+}
+
+/// An N-body simulation whose bodies are stored and integrated in `S`, `f64` by default.
+pub struct Simulation<S: Scalar = f64> {
+    buffer0: Vec<Body<S>>,
+    buffer1: Vec<Body<S>>,
     current_buffer: SimulationBuffer,
+    integrator: Box<dyn Integrator<S>>,
+    forces: ForceModel<S>,
+    collision_mode: CollisionMode,
+    merge_events: Vec<MergeEvent<S>>,
+    timestep: Timestep,
+    time: f64,
+    ticks: u64,
+    initial: ConservedQuantities<S>,
+    next_id: u64,
+    test_particles: Vec<TestParticle<S>>,
+    units: UnitSystem,
 }
 
-fn barycenter_for_bodies(bodies: &Vec<Body>) -> cgmath::Vector3<f64> 
+fn barycenter_for_bodies<S: Scalar>(bodies: &[Body<S>]) -> cgmath::Vector3<S> 
 {
-    let total_mass: f64 = bodies.iter().map(|b| b.mass).sum();    
-    bodies.iter().map(|b| b.mass * b.position).sum::<cgmath::Vector3<f64>>() / total_mass
+    let total_mass: S = bodies.iter().map(|b| b.mass).sum();    
+    bodies.iter().map(|b| b.position * b.mass).sum::<cgmath::Vector3<S>>() / total_mass
 }
 
-fn gravitational_force(a: &Body, b: &Body) -> f64 {
+fn kinetic_energy<S: Scalar>(bodies: &[Body<S>]) -> S {
+    bodies.iter().map(|b| S::from_f64(0.5) * b.mass * b.velocity.magnitude2()).sum()
+}
+
+fn potential_energy<S: Scalar>(bodies: &[Body<S>], forces: &ForceModel<S>) -> S {
+    let mut energy = S::zero();
+
+    for (i, a) in bodies.iter().enumerate() {
+        for b in &bodies[i + 1..] {
+            let distance = (b.position - a.position).magnitude();
+            energy -= forces.gravitational_constant * a.mass * b.mass * forces.softening.potential_kernel(distance);
+        }
+    }
+
+    energy
+}
+
+fn linear_momentum<S: Scalar>(bodies: &[Body<S>]) -> cgmath::Vector3<S> {
+    bodies.iter().map(|b| b.velocity * b.mass).sum()
+}
+
+fn angular_momentum<S: Scalar>(bodies: &[Body<S>]) -> cgmath::Vector3<S> {
+    if bodies.is_empty() {
+        return cgmath::Vector3::zero();
+    }
+
+    let total_mass: S = bodies.iter().map(|b| b.mass).sum();
+    let barycenter = barycenter_for_bodies(bodies);
+    let barycenter_velocity = linear_momentum(bodies) / total_mass;
+
+    bodies.iter().map(|b| {
+        (b.position - barycenter).cross(b.velocity - barycenter_velocity) * b.mass
+    }).sum()
+}
+
+fn gravitational_force<S: Scalar>(a: &Body<S>, b: &Body<S>, gravitational_constant: S) -> S {
     let displacement = a.position - b.position;
-    G * a.mass * b.mass / displacement.magnitude2()
+    gravitational_constant * a.mass * b.mass / displacement.magnitude2()
+}
+
+// acceleration at `position` due to a point mass at `source_position`
+fn point_mass_acceleration<S: Scalar>(
+    position: cgmath::Vector3<S>,
+    source_position: cgmath::Vector3<S>,
+    source_mass: S,
+    forces: &ForceModel<S>,
+) -> cgmath::Vector3<S> {
+    let displacement = source_position - position;
+
+    displacement * (forces.gravitational_constant * source_mass * forces.softening.force_kernel(displacement.magnitude2()))
+}
+
+fn direct_acceleration<S: Scalar>(bodies: &[Body<S>], index: usize, forces: &ForceModel<S>) -> cgmath::Vector3<S> {
+    let current = &bodies[index];
+
+    // near and far bodies' terms differ by orders of magnitude, so compensate when `forces.summation` says to
+    let mut acceleration = summation::VectorSum::new(forces.summation);
+    for (i, b) in bodies.iter().enumerate() {
+        if i != index {
+            acceleration.add(point_mass_acceleration(current.position, b.position, b.mass, forces));
+        }
+    }
+
+    acceleration.value()
+}
+
+// shortest dynamical timescale between any pair of bodies: the shorter of the free-fall time
+// sqrt(r^3 / G(m_a + m_b)) and the encounter time r / |v_a - v_b|, whether the pair is approaching or
+// receding, so that fast flybys are stepped finely on the way out as well as on the way in
+// (r is softened to sqrt(r^2 + epsilon^2) in both, or a softened head-on pass would need steps of zero)
+fn shortest_timescale<S: Scalar>(bodies: &[Body<S>], forces: &ForceModel<S>) -> Option<f64> {
+    let mut shortest: Option<S> = None;
+
+    for (i, a) in bodies.iter().enumerate() {
+        for b in &bodies[i + 1..] {
+            let separation = (b.position - a.position).magnitude();
+            let relative_speed = (b.velocity - a.velocity).magnitude();
+
+            // softening caps how deep the pair can fall into each other, and how close they pass
+            let softened_separation = separation.hypot(S::from_f64(forces.softening.length()));
+            let free_fall = (softened_separation.powi(3) / (forces.gravitational_constant * (a.mass + b.mass))).sqrt();
+            let encounter = softened_separation / relative_speed;
+            let timescale = free_fall.min(encounter);
+
+            shortest = Some(shortest.map_or(timescale, |s| s.min(timescale)));
+        }
+    }
+
+    shortest.map(S::into_f64)
+}
+
+// merges every pair of overlapping bodies in place: the survivor takes the combined mass and momentum,
+// and sits at the pair's center of mass
+fn merge_overlapping_bodies<S: Scalar>(bodies: &mut Vec<Body<S>>, time: f64, radius: impl Fn(&Body<S>) -> f64) -> Vec<MergeEvent<S>> {
+    let mut events = Vec::new();
+    let mut absorbed = vec![false; bodies.len()];
+
+    for i in 0..bodies.len() {
+        if absorbed[i] {
+            continue;
+        }
+
+        for j in i + 1..bodies.len() {
+            if absorbed[j] {
+                continue;
+            }
+
+            let (a, b) = (&bodies[i], &bodies[j]);
+            let contact_distance = S::from_f64(radius(a) + radius(b));
+
+            if (b.position - a.position).magnitude2() < contact_distance * contact_distance {
+                let (survivor, absorbed_body) = if a.mass >= b.mass { (a, b) } else { (b, a) };
+                let mass = a.mass + b.mass;
+                let merged = Body {
+                    id: survivor.id,
+                    name: survivor.name.clone(),
+                    position: (a.position * a.mass + b.position * b.mass) / mass,
+                    velocity: (a.velocity * a.mass + b.velocity * b.mass) / mass,
+                    mass,
+                    color: survivor.color,
+                    compensation: survivor.compensation.map(|_| Default::default()),
+                };
+
+                events.push(MergeEvent {
+                    time,
+                    survivor: survivor.id,
+                    absorbed: absorbed_body.id,
+                    masses: (survivor.mass, absorbed_body.mass),
+                    merged: merged.clone(),
+                });
+
+                // the merged body takes the survivor's own slot, which keeps the buffer sorted by id
+                if survivor.id == bodies[i].id {
+                    bodies[i] = merged;
+                    absorbed[j] = true;
+                } else {
+                    // the later body survives, and picks up any other overlaps when the outer loop reaches it
+                    bodies[j] = merged;
+                    absorbed[i] = true;
+                    break;
+                }
+            }
+        }
+    }
+
+    let mut absorbed = absorbed.into_iter();
+    bodies.retain(|_| !absorbed.next().unwrap());
+
+    events
 }
 
-fn orbital_velocity(a: &Body, b: &Body) -> cgmath::Vector3<f64> {
-    let gravitational_parameter = G * (a.mass + b.mass);
+fn orbital_velocity<S: Scalar>(a: &Body<S>, b: &Body<S>, gravitational_constant: S) -> cgmath::Vector3<S> {
+    let gravitational_parameter = gravitational_constant * (a.mass + b.mass);
     let displacement = b.position - a.position;
     let v = (gravitational_parameter / displacement.magnitude()).sqrt();
 
-    v * (b.mass / (a.mass + b.mass)) * displacement.normalize().cross(cgmath::Vector3::unit_z())
+    displacement.normalize().cross(cgmath::Vector3::unit_z()) * (v * (b.mass / (a.mass + b.mass)))
+}
+
+impl Default for Simulation {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Simulation {
     pub fn new() -> Self {
-        let body0 = Body {
-            position: (10.0, 0.0, 0.0).into(),
-            velocity: (0.0, 0.0, 0.0).into(),
-            mass: 10000000.0,
-        };
+        Self::with_integrator(Box::new(Leapfrog))
+    }
 
-        let body1 = Body {
-            position: -2.0 * body0.position,
-            velocity: (0.0, 0.0, 0.0).into(),
-            mass: body0.mass / 2.0,
-        };
+    pub fn with_integrator(integrator: Box<dyn Integrator>) -> Self {
+        let body0 = Body::new((10.0, 0.0, 0.0).into(), (0.0, 0.0, 0.0).into(), 10000000.0);
+        let body1 = Body::new(-2.0 * body0.position, (0.0, 0.0, 0.0).into(), body0.mass / 2.0);
 
         let mut bodies = vec![body0, body1];
-        bodies[0].velocity = orbital_velocity(&bodies[0], &bodies[1]);
-        bodies[1].velocity = orbital_velocity(&bodies[1], &bodies[0]);
+        bodies[0].velocity = orbital_velocity(&bodies[0], &bodies[1], G);
+        bodies[1].velocity = orbital_velocity(&bodies[1], &bodies[0], G);
+
+        Self::from_bodies(bodies, integrator, UnitSystem::default())
+    }
+}
+
+impl<S: Scalar> Simulation<S> {
+    /// A simulation of `bodies`, which are given ids in order and must already be in `units`, with
+    /// direct summation and no softening or collisions.
+    pub fn from_bodies(mut bodies: Vec<Body<S>>, integrator: Box<dyn Integrator<S>>, units: UnitSystem) -> Self {
+        for (i, body) in bodies.iter_mut().enumerate() {
+            body.id = BodyId(i as u64);
+            body.compensation = None;
+        }
+
+        let forces = ForceModel {
+            gravitational_constant: S::from_f64(units.gravitational_constant()),
+            solver: ForceSolver::Direct,
+            parallelism: Parallelism::Sequential,
+            softening: Softening::None,
+            summation: Summation::Naive,
+        };
 
         Simulation {
+            next_id: bodies.len() as u64,
+            initial: ConservedQuantities::of(&bodies, &forces),
             buffer0: bodies.clone(),
             buffer1: bodies,
             current_buffer: SimulationBuffer::Buffer0,
+            integrator,
+            forces,
+            collision_mode: CollisionMode::None,
+            merge_events: Vec::new(),
+            timestep: Timestep::Fixed(1.0),
+            time: 0.0,
+            ticks: 0,
+            test_particles: Vec::new(),
+            units,
+        }
+    }
+
+    /// The units every quantity going into or coming out of the simulation is in. Use
+    /// `units().conversion(...)` to read results in others.
+    pub fn units(&self) -> UnitSystem {
+        self.units
+    }
+
+    pub fn set_force_solver(&mut self, force_solver: ForceSolver) {
+        self.forces.solver = force_solver;
+    }
+
+    /// All bodies, in order of id.
+    pub fn bodies(&self) -> &[Body<S>] {
+        self.current_buffer()
+    }
+
+    pub fn body(&self, id: BodyId) -> Option<&Body<S>> {
+        let bodies = self.current_buffer();
+        bodies.binary_search_by_key(&id, |b| b.id).ok().map(|index| &bodies[index])
+    }
+
+    // bodies are only ever appended with a fresh id or removed, so both buffers stay sorted by id
+    fn insert_body(&mut self, mut body: Body<S>) -> BodyId {
+        body.id = BodyId(self.next_id);
+        body.compensation = self.compensation();
+        self.next_id += 1;
+
+        self.buffer0.push(body.clone());
+        self.buffer1.push(body.clone());
+
+        // the system has changed, so drifts are measured from here on
+        self.initial = ConservedQuantities::of(self.current_buffer(), &self.forces);
+
+        body.id
+    }
+
+    pub fn remove_body(&mut self, id: BodyId) -> Option<Body<S>> {
+        let index = self.current_buffer().binary_search_by_key(&id, |b| b.id).ok()?;
+
+        let (current_buffer, other_buffer) = match self.current_buffer {
+            SimulationBuffer::Buffer0 => (&mut self.buffer0, &mut self.buffer1),
+            SimulationBuffer::Buffer1 => (&mut self.buffer1, &mut self.buffer0),
+        };
+        other_buffer.remove(index);
+        let body = current_buffer.remove(index);
+
+        self.initial = ConservedQuantities::of(self.current_buffer(), &self.forces);
+
+        Some(body)
+    }
+
+    /// Fails for `Merge` in units that give bodies no size, see `UnitSystem::collision_radius`.
+    pub fn set_collision_mode(&mut self, collision_mode: CollisionMode) -> anyhow::Result<()> {
+        if collision_mode == CollisionMode::Merge && self.units.collision_radius(1.0).is_none() {
+            anyhow::bail!("bodies cannot merge in {:?}, which give them no size", self.units);
+        }
+
+        self.collision_mode = collision_mode;
+        Ok(())
+    }
+
+    /// Returns the merges that have happened since this was last called.
+    pub fn take_merge_events(&mut self) -> Vec<MergeEvent<S>> {
+        std::mem::take(&mut self.merge_events)
+    }
+
+    pub fn set_softening(&mut self, softening: Softening) {
+        self.forces.softening = softening;
+
+        // softening changes the potential, so drifts are measured from here on
+        self.initial = ConservedQuantities::of(self.current_buffer(), &self.forces);
+    }
+
+    /// Switches compensated summation on or off, for forces and for the built-in integrators'
+    /// updates. Custom integrators sum as they like.
+    pub fn set_summation(&mut self, summation: Summation) {
+        self.forces.summation = summation;
+
+        for body in self.buffer0.iter_mut().chain(self.buffer1.iter_mut()) {
+            match summation {
+                Summation::Naive => body.compensation = None,
+                Summation::Compensated => {
+                    body.compensation.get_or_insert_with(Default::default);
+                }
+            }
         }
     }
 
-    pub fn barycenter(&self) -> cgmath::Vector3<f64> {
-        barycenter_for_bodies(&self.current_buffer())
+    pub fn summation(&self) -> Summation {
+        self.forces.summation
+    }
+
+    // what each body starts out carrying under the current summation
+    fn compensation(&self) -> Option<summation::Compensation<S>> {
+        match self.forces.summation {
+            Summation::Naive => None,
+            Summation::Compensated => Some(Default::default()),
+        }
+    }
+
+    #[cfg(feature = "parallel")]
+    pub fn set_parallelism(&mut self, parallelism: Parallelism) {
+        self.forces.parallelism = parallelism;
+    }
+
+    pub fn set_timestep(&mut self, timestep: Timestep) {
+        self.timestep = timestep;
+    }
+
+    /// Total simulated time elapsed since the simulation was built.
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    /// Number of ticks taken since the simulation was built.
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    /// The step size the next `tick()` will take, given the current state.
+    pub fn dt(&self) -> f64 {
+        match self.timestep {
+            Timestep::Fixed(dt) => dt,
+            Timestep::Adaptive { eta, min, max } => match shortest_timescale(self.current_buffer(), &self.forces) {
+                Some(timescale) => (eta * timescale).max(min).min(max),
+                None => max,
+            },
+        }
+    }
+
+    pub fn kinetic_energy(&self) -> S {
+        kinetic_energy(self.current_buffer())
+    }
+
+    pub fn potential_energy(&self) -> S {
+        potential_energy(self.current_buffer(), &self.forces)
+    }
+
+    pub fn linear_momentum(&self) -> cgmath::Vector3<S> {
+        linear_momentum(self.current_buffer())
+    }
+
+    /// Angular momentum about `barycenter()`, measured in the barycentric frame.
+    pub fn angular_momentum(&self) -> cgmath::Vector3<S> {
+        angular_momentum(self.current_buffer())
+    }
+
+    /// The conserved quantities as `f64`, with drifts worked out in `S` first so that they stay
+    /// meaningful below `f64` precision.
+    pub fn diagnostics(&self) -> Diagnostics {
+        let kinetic_energy = self.kinetic_energy();
+        let potential_energy = self.potential_energy();
+        let linear_momentum = self.linear_momentum();
+        let angular_momentum = self.angular_momentum();
+
+        Diagnostics {
+            time: self.time,
+            kinetic_energy: kinetic_energy.into_f64(),
+            potential_energy: potential_energy.into_f64(),
+            linear_momentum: linear_momentum.map(S::into_f64),
+            angular_momentum: angular_momentum.map(S::into_f64),
+            energy_drift: relative_drift(kinetic_energy + potential_energy - self.initial.energy, self.initial.energy)
+                .into_f64(),
+            linear_momentum_drift: relative_drift(
+                (linear_momentum - self.initial.linear_momentum).magnitude(),
+                self.initial.momentum_scale,
+            ).into_f64(),
+            angular_momentum_drift: relative_drift(
+                (angular_momentum - self.initial.angular_momentum).magnitude(),
+                self.initial.angular_momentum.magnitude(),
+            ).into_f64(),
+        }
+    }
+
+    /// Returns a diagnostics sample on every `n`th tick, and `None` otherwise.
+    pub fn diagnostics_every(&self, n: u64) -> Option<Diagnostics> {
+        if n != 0 && self.ticks.is_multiple_of(n) {
+            Some(self.diagnostics())
+        } else {
+            None
+        }
+    }
+
+    pub fn barycenter(&self) -> cgmath::Vector3<S> {
+        barycenter_for_bodies(self.current_buffer())
         // cgmath::Vector3::zero()
     }
 
-    pub fn instances(&self) -> Vec<crate::render::Instance> {
+    #[cfg(feature = "render")]
+    pub fn instances(&self) -> Vec<Instance> {
         let buffer = match self.current_buffer {
             SimulationBuffer::Buffer0 => &self.buffer0,
             SimulationBuffer::Buffer1 => &self.buffer1,
@@ -95,30 +998,38 @@ impl Simulation {
 
         buffer.iter().map(|body| {
             Instance {
-                position: cgmath::vec3(body.position.x as f32, body.position.y as f32, body.position.z as f32),
+                id: body.id,
+                position: body.position.map(|x| x.into_f64() as f32),
                 rotation: cgmath::Quaternion::from_axis_angle(cgmath::Vector3::unit_z(), cgmath::Deg(0.0)),
-                color: BODY_COLOR,
-                scale: (body.mass.log10() / 7.0) as f32,
+                color: body.color,
+                scale: body.radius() as f32,
             }
         }).collect::<Vec<_>>()
     }
     
-    pub fn add_body_at_position(&mut self, barycentric_position: cgmath::Vector3<f64>, mass: BodyMass) {
-        let mut new_body = Body {
-            position: barycentric_position,
-            velocity: cgmath::Vector3::zero(),
-            mass: match mass {
+    pub fn add_body_at_position(&mut self, barycentric_position: cgmath::Vector3<S>, mass: BodyMass, direction: OrbitDirection) -> BodyId {
+        let mass = match self.units {
+            UnitSystem::Dimensionless { .. } => S::from_f64(match mass {
                 BodyMass::Small => 10.0,
                 BodyMass::Medium => 1000.0,
                 BodyMass::Large => 100000.0,
-            },
+            }),
+            _ => {
+                let total: S = self.current_buffer().iter().map(|b| b.mass).sum();
+                total * S::from_f64(match mass {
+                    BodyMass::Small => 1e-6,
+                    BodyMass::Medium => 1e-4,
+                    BodyMass::Large => 1e-2,
+                })
+            }
         };
+        let mut new_body = Body::new(barycentric_position, cgmath::Vector3::zero(), mass);
 
         // get current bodies and sort (greatest-to-least) by gravitational force at this point
         let mut existing_bodies = self.current_buffer().clone();
         existing_bodies.sort_unstable_by(|a, b| {
-            let force_a = gravitational_force(a, &new_body);
-            let force_b = gravitational_force(b, &new_body);
+            let force_a = gravitational_force(a, &new_body, self.forces.gravitational_constant);
+            let force_b = gravitational_force(b, &new_body, self.forces.gravitational_constant);
             force_b.partial_cmp(&force_a).unwrap()
         });
 
@@ -129,7 +1040,7 @@ impl Simulation {
             1 => Some(&existing_bodies[0]),
             _ => {
                 let attractor_separation = (existing_bodies[0].position - existing_bodies[1].position).magnitude();
-                let viable_orbit_radius = attractor_separation / 5.0;
+                let viable_orbit_radius = attractor_separation / S::from_f64(5.0);
 
                 let distance0 = (new_body.position - existing_bodies[0].position).magnitude();
                 let distance1 = (new_body.position - existing_bodies[1].position).magnitude();
@@ -144,27 +1055,31 @@ impl Simulation {
             }
         };
 
+        let sign = S::from_f64(match direction {
+            OrbitDirection::Prograde => 1.0,
+            OrbitDirection::Retrograde => -1.0,
+        });
+
         new_body.velocity = match orbit_target {
             Some(target) => {
-                orbital_velocity(&new_body, target) + target.velocity
+                orbital_velocity(&new_body, target, self.forces.gravitational_constant) * sign + target.velocity
             }
             None => {
                 // pretend barycenter is a point mass
-                let temp_barycenter = Body {
-                    position: self.barycenter(),
-                    velocity: cgmath::Vector3::zero(),
-                    mass: self.current_buffer().iter().map(|b| b.mass).sum(),
-                };
+                let temp_barycenter = Body::new(
+                    self.barycenter(),
+                    cgmath::Vector3::zero(),
+                    self.current_buffer().iter().map(|b| b.mass).sum(),
+                );
 
-                orbital_velocity(&new_body, &temp_barycenter)
+                orbital_velocity(&new_body, &temp_barycenter, self.forces.gravitational_constant) * sign
             }
         };
 
-        self.buffer0.push(new_body.clone());
-        self.buffer1.push(new_body);
+        self.insert_body(new_body)
     }
 
-    fn current_buffer(&self) -> &Vec<Body> {
+    fn current_buffer(&self) -> &Vec<Body<S>> {
         match self.current_buffer {
             SimulationBuffer::Buffer0 => &self.buffer0,
             SimulationBuffer::Buffer1 => &self.buffer1,
@@ -172,34 +1087,54 @@ impl Simulation {
     }
 
     pub fn tick(&mut self) {
+        let dt = self.dt();
+
         let (current_buffer, next_buffer) = match self.current_buffer {
             SimulationBuffer::Buffer0 => (&self.buffer0, &mut self.buffer1),
             SimulationBuffer::Buffer1 => (&self.buffer1, &mut self.buffer0),
         };
 
-        // this is not as accurate as it could be, but it is fast.
-        // in the future, could consider sorting intermediate values to sum the smaller values first
-        for (current, next) in current_buffer.iter().zip(next_buffer.iter_mut()) {
-            next.velocity = current_buffer.iter().fold(current.velocity, |velocity_acc, b| {
-                if b != current {
-                    let displacement = b.position - current.position;
-                    let force = gravitational_force(current, b);
-                    let d_velocity = force / current.mass;
+        let forces = self.forces;
+        self.integrator.step(current_buffer, next_buffer, S::from_f64(dt), &|bodies| forces.accelerations(bodies));
 
-                    velocity_acc + d_velocity * displacement.normalize()
-                } else {
-                    velocity_acc
-                }
-            });
-
-            next.position = current.position + next.velocity;
+        if !self.test_particles.is_empty() {
+            particles::step_test_particles(&mut self.test_particles, current_buffer, next_buffer, S::from_f64(dt), &forces);
         }
-
+        self.time += dt;
+        self.ticks += 1;
 
         self.current_buffer = match self.current_buffer {
             SimulationBuffer::Buffer0 => SimulationBuffer::Buffer1,
             SimulationBuffer::Buffer1 => SimulationBuffer::Buffer0,
         };
+
+        if self.collision_mode == CollisionMode::Merge {
+            self.merge_collisions();
+        }
+    }
+
+    fn merge_collisions(&mut self) {
+        let time = self.time;
+        let units = self.units;
+        let (current_buffer, other_buffer) = match self.current_buffer {
+            SimulationBuffer::Buffer0 => (&mut self.buffer0, &mut self.buffer1),
+            SimulationBuffer::Buffer1 => (&mut self.buffer1, &mut self.buffer0),
+        };
+
+        // set_collision_mode only allows merging in units with a collision radius
+        let events = merge_overlapping_bodies(current_buffer, time, |body| {
+            units.collision_radius(body.mass.into_f64()).unwrap_or(0.0)
+        });
+        if events.is_empty() {
+            return;
+        }
+
+        // keep both buffers the same length, so the next step has somewhere to write every body
+        other_buffer.clone_from(current_buffer);
+        self.merge_events.extend(events);
+
+        // merging is inelastic, so drifts are measured from here on
+        self.initial = ConservedQuantities::of(self.current_buffer(), &self.forces);
     }
 
     fn _debug_print_simulation_frame(&self) {
@@ -209,4 +1144,167 @@ impl Simulation {
         }
         println!("END SIMULATION FRAME");
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STEPS_PER_PERIOD: u64 = 2000;
+
+    // an e = 0.5 binary of unequal masses, started at periapsis, with its relative orbit's mu
+    fn kepler_binary() -> (Vec<Body>, f64) {
+        let (m1, m2, a, e) = (1e7, 2e6, 10.0, 0.5);
+        let mu = G * (m1 + m2);
+        let position = cgmath::vec3(a * (1.0 - e), 0.0, 0.0);
+        let velocity = cgmath::vec3(0.0, (mu * (1.0 + e) / (a * (1.0 - e))).sqrt(), 0.0);
+
+        let bodies = vec![
+            Body::new(position * (-m2 / (m1 + m2)), velocity * (-m2 / (m1 + m2)), m1),
+            Body::new(position * (m1 / (m1 + m2)), velocity * (m1 / (m1 + m2)), m2),
+        ];
+        (bodies, mu)
+    }
+
+    // distance from the analytic relative orbit after one period, over the semi-major axis
+    fn kepler_error(integrator: Box<dyn Integrator>) -> f64 {
+        let (bodies, mu) = kepler_binary();
+        let (position, velocity) = (bodies[1].position - bodies[0].position, bodies[1].velocity - bodies[0].velocity);
+        let a = 10.0;
+        let period = 2.0 * std::f64::consts::PI * (a * a * a / mu).sqrt();
+
+        let mut simulation = Simulation::from_bodies(bodies, integrator, UnitSystem::default());
+        simulation.set_timestep(Timestep::Fixed(period / STEPS_PER_PERIOD as f64));
+        for _ in 0..STEPS_PER_PERIOD {
+            simulation.tick();
+        }
+
+        let (expected, _) = propagate_kepler(position, velocity, mu, simulation.time());
+        let bodies = simulation.bodies();
+        (bodies[1].position - bodies[0].position - expected).magnitude() / a
+    }
+
+    #[test]
+    fn integrators_follow_a_kepler_orbit() {
+        // a few times the error each order leaves at this resolution: about 1e-3 for first-order
+        // Euler, 4e-4 for the second-order pair and 2e-9 for fourth-order RK4
+        let cases: [(Box<dyn Integrator>, f64); 4] = [
+            (Box::new(Euler), 5e-3),
+            (Box::new(Leapfrog), 2e-3),
+            (Box::new(VelocityVerlet), 2e-3),
+            (Box::new(Rk4), 1e-8),
+        ];
+
+        for (integrator, tolerance) in cases {
+            let kind = integrator.kind();
+            let error = kepler_error(integrator);
+            assert!(error < tolerance, "{:?}: error {:e} over one period", kind, error);
+        }
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn deterministic_parallel_forces_match_sequential_ones_exactly() {
+        let run = |parallelism: Parallelism| {
+            let bodies = plummer_sphere(300, 11).unwrap();
+            let mut simulation = Simulation::from_bodies(bodies, Box::new(Leapfrog), UnitSystem::NBody);
+            simulation.set_parallelism(parallelism);
+            simulation.set_timestep(Timestep::Fixed(1e-3));
+            for _ in 0..50 {
+                simulation.tick();
+            }
+            simulation.bodies().iter().map(|b| (b.position, b.velocity)).collect::<Vec<_>>()
+        };
+
+        assert_eq!(run(Parallelism::Sequential), run(Parallelism::Parallel { deterministic: true }));
+    }
+
+    #[test]
+    fn merging_into_a_later_heavier_body_keeps_ids_sorted() {
+        let bodies = vec![
+            Body::new(cgmath::vec3(0.0, 0.0, 0.0), cgmath::Vector3::zero(), 10.0),
+            Body::new(cgmath::vec3(1000.0, 0.0, 0.0), cgmath::Vector3::zero(), 10.0),
+            Body::new(cgmath::vec3(0.5, 0.0, 0.0), cgmath::Vector3::zero(), 100000.0),
+        ];
+        let mut simulation = Simulation::from_bodies(bodies, Box::new(Leapfrog), UnitSystem::default());
+        let ids: Vec<_> = simulation.bodies().iter().map(|b| b.id()).collect();
+        simulation.set_collision_mode(CollisionMode::Merge).unwrap();
+        simulation.tick();
+
+        let events = simulation.take_merge_events();
+        assert_eq!(events.len(), 1);
+        assert_eq!((events[0].survivor, events[0].absorbed), (ids[2], ids[0]));
+
+        let remaining: Vec<_> = simulation.bodies().iter().map(|b| b.id()).collect();
+        assert_eq!(remaining, vec![ids[1], ids[2]]);
+        for id in remaining {
+            assert_eq!(simulation.body(id).map(|b| b.id()), Some(id));
+        }
+        assert_eq!(simulation.body(ids[2]).unwrap().mass, 100010.0);
+        assert!(simulation.body(ids[0]).is_none());
+    }
+
+    // RK4 on the viewer's binary, whose truncation error per step is far below f64's rounding
+    fn drift_in<S: Scalar>() -> f64 {
+        let bodies = Simulation::new().bodies().iter().map(Body::cast::<S>).collect();
+        let mut simulation = Simulation::<S>::from_bodies(bodies, Box::new(Rk4), UnitSystem::default());
+        simulation.set_timestep(Timestep::Fixed(0.25));
+        for _ in 0..10000 {
+            simulation.tick();
+        }
+        simulation.diagnostics().energy_drift.abs()
+    }
+
+    #[test]
+    fn more_precise_scalars_drift_less() {
+        let (double_double, double, single) = (drift_in::<scalar::DoubleDouble>(), drift_in::<f64>(), drift_in::<f32>());
+        assert!(double_double * 100.0 < double, "{:e} vs {:e}", double_double, double);
+        assert!(double * 100.0 < single, "{:e} vs {:e}", double, single);
+    }
+
+    #[test]
+    fn merging_needs_units_that_give_bodies_a_size() {
+        let bodies = vec![
+            Body::new(cgmath::vec3(0.0, 0.0, 0.0), cgmath::Vector3::zero(), 1.0),
+            Body::new(cgmath::vec3(0.001, 0.0, 0.0), cgmath::Vector3::zero(), 1.0),
+        ];
+
+        let mut nbody = Simulation::from_bodies(bodies.clone(), Box::new(Leapfrog), UnitSystem::NBody);
+        assert!(nbody.set_collision_mode(CollisionMode::Merge).is_err());
+
+        // two suns a thousandth of an AU apart overlap, though neither is heavier than a unit of mass
+        let mut astronomical = Simulation::from_bodies(bodies, Box::new(Leapfrog), UnitSystem::Astronomical);
+        astronomical.set_collision_mode(CollisionMode::Merge).unwrap();
+        astronomical.set_timestep(Timestep::Fixed(1e-6));
+        astronomical.tick();
+        assert_eq!(astronomical.take_merge_events().len(), 1);
+        assert_eq!(astronomical.bodies().len(), 1);
+    }
+
+    #[test]
+    fn clicked_bodies_are_weighed_against_the_system_outside_the_default_units() {
+        let sun = Body::new(cgmath::Vector3::zero(), cgmath::Vector3::zero(), 1.0);
+        let mut simulation = Simulation::from_bodies(vec![sun], Box::new(Leapfrog), UnitSystem::Astronomical);
+        let id = simulation.add_body_at_position(cgmath::vec3(1.0, 0.0, 0.0), BodyMass::Large, OrbitDirection::Prograde);
+        assert_eq!(simulation.body(id).unwrap().mass, 0.01);
+    }
+
+    #[test]
+    fn adaptive_steps_get_through_a_softened_head_on_pass() {
+        for softening in [Softening::Plummer(0.1), Softening::Spline(0.1)] {
+            let bodies = vec![
+                Body::new(cgmath::vec3(-1.0, 0.0, 0.0), cgmath::Vector3::zero(), 1.0),
+                Body::new(cgmath::vec3(1.0, 0.0, 0.0), cgmath::Vector3::zero(), 1.0),
+            ];
+            let mut simulation = Simulation::from_bodies(bodies, Box::new(Leapfrog), UnitSystem::NBody);
+            simulation.set_softening(softening);
+            simulation.set_timestep(Timestep::Adaptive { eta: 0.05, min: 0.0, max: 0.1 });
+
+            // they meet at about t = 2.2, pass through each other and separate again
+            while simulation.time() < 5.0 {
+                assert!(simulation.ticks() < 10000, "{:?}: stuck at t = {}", softening, simulation.time());
+                simulation.tick();
+            }
+        }
+    }
+}
@@ -0,0 +1,165 @@
+// full simulation state, saved so a run can be continued later
+
+use anyhow::*;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use super::{
+    Body, CollisionMode, ConservedQuantities, ForceModel, ForceSolver, IntegratorKind, Parallelism, Simulation,
+    SimulationBuffer, Softening, Summation, TestParticle, Timestep, UnitSystem,
+};
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Checkpoint {
+    time: f64,
+    ticks: u64,
+    next_id: u64,
+    /// which of the two buffers held the current state, 0 or 1
+    active_buffer: u8,
+    integrator: IntegratorKind,
+    gravitational_constant: f64,
+    /// missing from older checkpoints, whose units are dimensionless
+    #[serde(default)]
+    units: Option<UnitSystem>,
+    force_solver: ForceSolver,
+    softening: Softening,
+    #[serde(default)]
+    summation: Summation,
+    collisions: CollisionMode,
+    timestep: Timestep,
+    initial: ConservedQuantities,
+    bodies: Vec<Body>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    test_particles: Vec<TestParticle>,
+}
+
+impl Simulation {
+    /// Writes the full state to `path`. Loading it again continues the exact same trajectory.
+    ///
+    /// Fails if the simulation uses an integrator that isn't one of the built-in `IntegratorKind`s.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let integrator = self.integrator.kind()
+            .ok_or_else(|| anyhow!("only simulations using a built-in integrator can be checkpointed"))?;
+
+        let checkpoint = Checkpoint {
+            time: self.time,
+            ticks: self.ticks,
+            next_id: self.next_id,
+            active_buffer: match self.current_buffer {
+                SimulationBuffer::Buffer0 => 0,
+                SimulationBuffer::Buffer1 => 1,
+            },
+            integrator,
+            gravitational_constant: self.forces.gravitational_constant,
+            units: Some(self.units),
+            force_solver: self.forces.solver,
+            softening: self.forces.softening,
+            summation: self.forces.summation,
+            collisions: self.collision_mode,
+            timestep: self.timestep,
+            initial: self.initial,
+            bodies: self.current_buffer().clone(),
+            test_particles: self.test_particles.clone(),
+        };
+
+        // going through a Value lets toml put plain values ahead of tables, whatever the field order
+        let contents = toml::to_string(&toml::Value::try_from(&checkpoint)?)?;
+        std::fs::write(path.as_ref(), contents)
+            .with_context(|| format!("could not write checkpoint {:?}", path.as_ref()))
+    }
+
+    /// Restores a simulation written by `save`. Runs on a single thread until told otherwise.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let contents = std::fs::read_to_string(path.as_ref())
+            .with_context(|| format!("could not read checkpoint {:?}", path.as_ref()))?;
+        let checkpoint: Checkpoint = toml::from_str(&contents)
+            .with_context(|| format!("invalid checkpoint {:?}", path.as_ref()))?;
+
+        let current_buffer = match checkpoint.active_buffer {
+            0 => SimulationBuffer::Buffer0,
+            1 => SimulationBuffer::Buffer1,
+            other => bail!("invalid active buffer {} in checkpoint {:?}", other, path.as_ref()),
+        };
+
+        let mut simulation = Simulation {
+            buffer0: checkpoint.bodies.clone(),
+            buffer1: checkpoint.bodies,
+            current_buffer,
+            integrator: checkpoint.integrator.integrator(),
+            forces: ForceModel {
+                gravitational_constant: checkpoint.gravitational_constant,
+                solver: checkpoint.force_solver,
+                parallelism: Parallelism::Sequential,
+                softening: checkpoint.softening,
+                summation: checkpoint.summation,
+            },
+            collision_mode: CollisionMode::None,
+            merge_events: Vec::new(),
+            timestep: checkpoint.timestep,
+            time: checkpoint.time,
+            ticks: checkpoint.ticks,
+            initial: checkpoint.initial,
+            next_id: checkpoint.next_id,
+            test_particles: checkpoint.test_particles,
+            units: checkpoint.units.unwrap_or(UnitSystem::Dimensionless {
+                gravitational_constant: checkpoint.gravitational_constant,
+            }),
+        };
+        simulation.set_collision_mode(checkpoint.collisions)
+            .with_context(|| format!("invalid checkpoint {:?}", path.as_ref()))?;
+
+        Ok(simulation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::Preset;
+
+    // everything a continued run could differ in; bodies compare by id alone, so their state is spelled out
+    #[derive(Debug, PartialEq)]
+    struct State {
+        time: f64,
+        ticks: u64,
+        bodies: Vec<(cgmath::Vector3<f64>, cgmath::Vector3<f64>, f64)>,
+        test_particles: Vec<TestParticle>,
+    }
+
+    fn state(simulation: &Simulation) -> State {
+        State {
+            time: simulation.time(),
+            ticks: simulation.ticks(),
+            bodies: simulation.bodies().iter().map(|b| (b.position, b.velocity, b.mass)).collect(),
+            test_particles: simulation.test_particles().to_vec(),
+        }
+    }
+
+    #[test]
+    fn restarting_from_a_checkpoint_continues_the_same_trajectory() {
+        let mut simulation = Simulation::from_preset(Preset::FigureEight);
+        simulation.set_timestep(Timestep::Adaptive { eta: 0.01, min: 0.0, max: 1.0 });
+        simulation.set_summation(Summation::Compensated);
+        simulation.add_test_particle(TestParticle::new(cgmath::vec3(30.0, 0.0, 0.0), cgmath::vec3(0.0, 0.05, 0.0)));
+
+        for _ in 0..150 {
+            simulation.tick();
+        }
+
+        let path = std::env::temp_dir().join(format!("nbody-checkpoint-test-{}.toml", std::process::id()));
+        simulation.save(&path).unwrap();
+        let restored = Simulation::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        let mut restored = restored.unwrap();
+
+        for _ in 0..300 {
+            simulation.tick();
+            restored.tick();
+        }
+
+        assert_eq!(state(&restored), state(&simulation));
+        assert_eq!(restored.summation(), Summation::Compensated);
+        assert_eq!(restored.diagnostics().energy_drift, simulation.diagnostics().energy_drift);
+    }
+}
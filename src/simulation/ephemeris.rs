@@ -0,0 +1,147 @@
+// snapshots of real systems: bodies' gravitational parameters and barycentric state vectors at one epoch
+
+use anyhow::*;
+use serde::Deserialize;
+use std::path::Path;
+
+use super::units::GM_SUN;
+use super::{Body, Rk4, Simulation, Timestep, UnitSystem};
+
+// ephemeris files give positions in au, velocities in au/day and GM in km^3/s^2
+const KM3_PER_S2: f64 = 1e9;
+
+// a hundredth of a day, about 180 steps per orbit of Io, the fastest of the bundled moons
+const STEP: f64 = 0.01;
+
+/// Bodies at a fixed epoch, as in `res/ephemerides/solar-system.toml`.
+///
+/// ```toml
+/// epoch = "J2000.0"
+///
+/// [[body]]
+/// name = "Sun"
+/// gm = 132712440041.939
+/// position = [-7.1355e-03, -2.7956e-03, 2.0612e-04]
+/// velocity = [5.3786e-06, -7.4000e-06, -9.4324e-08]
+/// ```
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Ephemeris {
+    pub epoch: String,
+    #[serde(rename = "body")]
+    pub bodies: Vec<EphemerisBody>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EphemerisBody {
+    pub name: String,
+    /// gravitational parameter, km^3/s^2
+    pub gm: f64,
+    /// au
+    pub position: [f64; 3],
+    /// au/day
+    pub velocity: [f64; 3],
+}
+
+impl std::str::FromStr for Ephemeris {
+    type Err = Error;
+
+    fn from_str(source: &str) -> Result<Self> {
+        let ephemeris: Ephemeris = toml::from_str(source)?;
+
+        for body in &ephemeris.bodies {
+            if body.gm.is_nan() || body.gm <= 0.0 {
+                bail!("{} must have a positive gm", body.name);
+            }
+        }
+
+        Ok(ephemeris)
+    }
+}
+
+impl Ephemeris {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let source = std::fs::read_to_string(path.as_ref())
+            .with_context(|| format!("could not read ephemeris {:?}", path.as_ref()))?;
+
+        source.parse().with_context(|| format!("invalid ephemeris {:?}", path.as_ref()))
+    }
+
+    /// The bodies, named, with positions, velocities and masses in `units`, which must be physical.
+    pub fn bodies(&self, units: UnitSystem) -> Result<Vec<Body>> {
+        let conversion = UnitSystem::Astronomical.conversion(units)?;
+
+        Ok(self.bodies.iter().map(|description| {
+            let mut body = Body::new(description.position.into(), description.velocity.into(), description.gm * KM3_PER_S2 / GM_SUN);
+            body.name = Some(description.name.clone());
+            conversion.body(&body)
+        }).collect())
+    }
+}
+
+impl Simulation {
+    /// A simulation of `ephemeris` in `units`, which must be physical, stepped with RK4 a hundredth
+    /// of a day at a time.
+    pub fn from_ephemeris(ephemeris: &Ephemeris, units: UnitSystem) -> Result<Self> {
+        let mut simulation = Simulation::from_bodies(ephemeris.bodies(units)?, Box::new(Rk4), units);
+        simulation.set_timestep(Timestep::Fixed(STEP * UnitSystem::Astronomical.conversion(units)?.time));
+        Ok(simulation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    // in days; the Moon and the other planets move Earth's period by a few thousandths of a day
+    const SIDEREAL_YEAR: f64 = 365.256363;
+
+    // days until Earth has gone once around the Sun, as seen from the Sun in the ecliptic plane
+    fn earth_orbital_period(units: UnitSystem) -> f64 {
+        let ephemeris = Ephemeris::load("res/ephemerides/solar-system.toml").unwrap();
+        let mut simulation = Simulation::from_ephemeris(&ephemeris, units).unwrap();
+        let day = simulation.units().time().unwrap() / 86400.0;
+
+        let find = |simulation: &Simulation, name: &str| {
+            simulation.bodies().iter().position(|b| b.name.as_deref() == Some(name)).unwrap()
+        };
+        let (sun, earth) = (find(&simulation, "Sun"), find(&simulation, "Earth"));
+        let angle = |simulation: &Simulation| {
+            let offset = simulation.bodies()[earth].position - simulation.bodies()[sun].position;
+            offset.y.atan2(offset.x)
+        };
+
+        let start = angle(&simulation);
+        let (mut swept, mut previous, mut previous_time) = (0.0, start, 0.0);
+
+        loop {
+            simulation.tick();
+            let current = angle(&simulation);
+            // unwrap across the branch cut; a step is a tiny fraction of a turn
+            let step = (current - previous + 3.0 * PI).rem_euclid(2.0 * PI) - PI;
+
+            if swept + step >= 2.0 * PI {
+                let fraction = (2.0 * PI - swept) / step;
+                return (previous_time + fraction * (simulation.time() - previous_time)) * day;
+            }
+
+            swept += step;
+            previous = current;
+            previous_time = simulation.time();
+        }
+    }
+
+    #[test]
+    fn earth_takes_a_sidereal_year_in_astronomical_units() {
+        let period = earth_orbital_period(UnitSystem::Astronomical);
+        assert!((period - SIDEREAL_YEAR).abs() < 0.01, "Earth went round in {} days", period);
+    }
+
+    #[test]
+    fn earth_takes_a_sidereal_year_in_si_units() {
+        let period = earth_orbital_period(UnitSystem::Si);
+        assert!((period - SIDEREAL_YEAR).abs() < 0.01, "Earth went round in {} days", period);
+    }
+}
@@ -0,0 +1,679 @@
+// seeded initial conditions for star clusters and galaxies
+//
+// Clusters are returned in standard (Henon) N-body units: G = 1, total mass 1 and total energy
+// -1/4, with the barycenter at rest at the origin. Run them with a gravitational constant of 1, or
+// scale velocities by sqrt(G) to use another one. Galaxies are built for a given G and size.
+
+use anyhow::*;
+use cgmath::prelude::*;
+use std::f64::consts::PI;
+
+use super::{kepler, Body};
+
+/// Small, fast, seedable generator (xoshiro256**), so the same seed always gives the same cluster.
+pub(super) struct Rng {
+    state: [u64; 4],
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // splitmix64 spreads the seed over the whole state, which must not be all zeroes
+        let mut seed = seed;
+        let mut next = || {
+            seed = seed.wrapping_add(0x9e3779b97f4a7c15);
+            let mut z = seed;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+            z ^ (z >> 31)
+        };
+
+        Rng { state: [next(), next(), next(), next()] }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let s = &mut self.state;
+        let result = s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = s[1] << 17;
+
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(45);
+
+        result
+    }
+
+    /// Uniform in [0, 1).
+    pub fn uniform(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 * (1.0 / (1u64 << 53) as f64)
+    }
+
+    /// Uniformly distributed direction.
+    pub fn unit_vector(&mut self) -> cgmath::Vector3<f64> {
+        let z = 2.0 * self.uniform() - 1.0;
+        let phi = 2.0 * PI * self.uniform();
+        let radius = (1.0 - z * z).sqrt();
+
+        cgmath::vec3(radius * phi.cos(), radius * phi.sin(), z)
+    }
+
+    /// Normally distributed, with zero mean and unit variance.
+    pub fn gaussian(&mut self) -> f64 {
+        // Box-Muller, with 1 - u in (0, 1] so the logarithm stays finite
+        (-2.0 * (1.0 - self.uniform()).ln()).sqrt() * (2.0 * PI * self.uniform()).cos()
+    }
+
+    fn gaussian_vector(&mut self) -> cgmath::Vector3<f64> {
+        cgmath::vec3(self.gaussian(), self.gaussian(), self.gaussian())
+    }
+}
+
+// shifts positions and velocities so that the barycenter is at rest at the origin
+fn move_to_barycenter(bodies: &mut [Body]) {
+    let total_mass: f64 = bodies.iter().map(|b| b.mass).sum();
+    let barycenter = bodies.iter().map(|b| b.mass * b.position).sum::<cgmath::Vector3<f64>>() / total_mass;
+    let velocity = bodies.iter().map(|b| b.mass * b.velocity).sum::<cgmath::Vector3<f64>>() / total_mass;
+
+    for body in bodies.iter_mut() {
+        body.position -= barycenter;
+        body.velocity -= velocity;
+    }
+}
+
+// Moves the bodies into the barycentric frame, then scales positions and velocities so that the
+// potential energy is exactly -1/2 and the kinetic energy 1/4 (G = 1), i.e. virial equilibrium at
+// an energy of -1/4.
+fn scale_to_nbody_units(bodies: &mut [Body]) {
+    move_to_barycenter(bodies);
+
+    let kinetic: f64 = bodies.iter().map(|b| 0.5 * b.mass * b.velocity.magnitude2()).sum();
+    let mut potential = 0.0;
+
+    for (i, a) in bodies.iter().enumerate() {
+        for b in &bodies[i + 1..] {
+            potential -= a.mass * b.mass / (a.position - b.position).magnitude();
+        }
+    }
+
+    let length_scale = potential / -0.5;
+    let velocity_scale = (0.25 / kinetic).sqrt();
+
+    for body in bodies.iter_mut() {
+        body.position *= length_scale;
+        body.velocity *= velocity_scale;
+    }
+}
+
+fn equal_mass_bodies(n: usize, mut sample: impl FnMut() -> (cgmath::Vector3<f64>, cgmath::Vector3<f64>)) -> Vec<Body> {
+    let mut bodies: Vec<Body> = (0..n).map(|_| {
+        let (position, velocity) = sample();
+        Body::new(position, velocity, 1.0 / n as f64)
+    }).collect();
+
+    scale_to_nbody_units(&mut bodies);
+    bodies
+}
+
+// the outermost 0.1% of a Plummer sphere's mass lies beyond ~39 scale radii, far enough out
+// that those few bodies would only ever escape
+const PLUMMER_MASS_CUTOFF: f64 = 0.999;
+
+/// `n` equal-mass bodies sampled from a Plummer sphere, after Aarseth, Henon & Wielen (1974).
+pub fn plummer_sphere(n: usize, seed: u64) -> Result<Vec<Body>> {
+    ensure!(n >= 2, "a cluster needs at least two bodies, not {}", n);
+    let mut rng = Rng::new(seed);
+
+    Ok(equal_mass_bodies(n, || {
+        // invert the cumulative mass M(r) = r^3 / (1 + r^2)^(3/2), with a = 1
+        let mass_fraction = PLUMMER_MASS_CUTOFF * (1.0 - rng.uniform());
+        let r = 1.0 / (mass_fraction.powf(-2.0 / 3.0) - 1.0).sqrt();
+
+        // speeds as a fraction q of escape speed follow q^2 (1 - q^2)^(7/2), whose maximum is below 0.1
+        let q = loop {
+            let q = rng.uniform();
+            if 0.1 * rng.uniform() < q * q * (1.0 - q * q).powf(3.5) {
+                break q;
+            }
+        };
+        let escape_speed = 2f64.sqrt() * (1.0 + r * r).powf(-0.25);
+
+        (r * rng.unit_vector(), q * escape_speed * rng.unit_vector())
+    }))
+}
+
+// radial steps per King radius when integrating the King model's potential
+const KING_STEPS_PER_RADIUS: f64 = 1000.0;
+
+// density of a King model relative to its central value, as a function of the dimensionless
+// potential W, up to a constant factor
+fn king_density(w: f64) -> f64 {
+    if w <= 0.0 {
+        return 0.0;
+    }
+
+    // exp(W) erf(sqrt W) - sqrt(4W / pi) (1 + 2W / 3)
+    w.exp() * erf(w.sqrt()) - (4.0 * w / PI).sqrt() * (1.0 + 2.0 * w / 3.0)
+}
+
+// the King density is a small difference near the tidal radius, so erf needs to be accurate to
+// rounding: the Taylor series for small arguments and the continued fraction for erfc otherwise
+fn erf(x: f64) -> f64 {
+    if x < 2.5 {
+        let mut term = x;
+        let mut sum = x;
+        let mut k = 0.0;
+
+        while term.abs() > 1e-17 * sum.abs() {
+            k += 1.0;
+            term *= -x * x / k;
+            sum += term / (2.0 * k + 1.0);
+        }
+
+        2.0 / PI.sqrt() * sum
+    } else {
+        // evaluated from the tail, erfc(x) = exp(-x^2) / sqrt(pi) / (x + 1/2 / (x + 1 / (x + 3/2 / (x + ...))))
+        let mut fraction = x;
+        for k in (1..60).rev() {
+            fraction = x + 0.5 * k as f64 / fraction;
+        }
+
+        1.0 - (-x * x).exp() / (PI.sqrt() * fraction)
+    }
+}
+
+/// Radius, dimensionless potential W and enclosed mass at each integration step out to the tidal
+/// radius of a King (1966) model with central potential `w0`, in units of the King radius.
+fn king_profile(w0: f64) -> Vec<(f64, f64, f64)> {
+    let central_density = king_density(w0);
+    let h = 1.0 / KING_STEPS_PER_RADIUS;
+
+    // W'' = -2 W' / r - 9 rho(W) / rho(W0), started from the series W = W0 - 3 r^2 / 2 near the center
+    let derivatives = |r: f64, (w, dw): (f64, f64)| (dw, -2.0 * dw / r - 9.0 * king_density(w) / central_density);
+
+    let mut r = h;
+    let mut state = (w0 - 1.5 * r * r, -3.0 * r);
+    // the enclosed mass is -r^2 W', up to a constant factor
+    let mut profile = vec![(0.0, w0, 0.0), (r, state.0, -r * r * state.1)];
+
+    while state.0 > 0.0 {
+        let k1 = derivatives(r, state);
+        let k2 = derivatives(r + 0.5 * h, (state.0 + 0.5 * h * k1.0, state.1 + 0.5 * h * k1.1));
+        let k3 = derivatives(r + 0.5 * h, (state.0 + 0.5 * h * k2.0, state.1 + 0.5 * h * k2.1));
+        let k4 = derivatives(r + h, (state.0 + h * k3.0, state.1 + h * k3.1));
+
+        state = (
+            state.0 + h / 6.0 * (k1.0 + 2.0 * k2.0 + 2.0 * k3.0 + k4.0),
+            state.1 + h / 6.0 * (k1.1 + 2.0 * k2.1 + 2.0 * k3.1 + k4.1),
+        );
+        r += h;
+        profile.push((r, state.0.max(0.0), -r * r * state.1));
+    }
+
+    profile
+}
+
+/// `n` equal-mass bodies sampled from a King (1966) model with dimensionless central potential
+/// `w0`, which sets how concentrated the cluster is (typically 1 to 12).
+pub fn king_model(n: usize, w0: f64, seed: u64) -> Result<Vec<Body>> {
+    ensure!(n >= 2, "a cluster needs at least two bodies, not {}", n);
+    ensure!(w0 > 0.0 && w0 <= 16.0, "King models need 0 < W0 <= 16, not {}", w0);
+
+    let profile = king_profile(w0);
+    let total_mass = profile.last().unwrap().2;
+    let mut rng = Rng::new(seed);
+
+    Ok(equal_mass_bodies(n, || {
+        // invert the cumulative mass profile, interpolating between integration steps
+        let target = total_mass * rng.uniform();
+        let i = profile.partition_point(|&(_, _, mass)| mass < target).clamp(1, profile.len() - 1);
+        let (r0, w0, m0) = profile[i - 1];
+        let (r1, w1, m1) = profile[i];
+        let t = if m1 > m0 { (target - m0) / (m1 - m0) } else { 0.0 };
+        let (r, w) = (r0 + t * (r1 - r0), w0 + t * (w1 - w0));
+
+        // speeds, in units of the velocity dispersion parameter, are distributed as
+        // v^2 (exp(W - v^2 / 2) - 1) up to the local escape speed sqrt(2W)
+        let escape_speed = (2.0 * w).sqrt();
+        let bound = if w >= 1.0 { 2.0 * (w - 1.0).exp() } else { 2.0 * w };
+        let speed = loop {
+            let v = escape_speed * rng.uniform();
+            if bound * rng.uniform() < v * v * ((w - 0.5 * v * v).exp() - 1.0) {
+                break v;
+            }
+        };
+
+        (r * rng.unit_vector(), speed * rng.unit_vector())
+    }))
+}
+
+// Galaxies are built in units of the disk's scale length and mass, with G = 1, and scaled at the end.
+// The disk, bulge and halo are each cut off where they enclose these fractions of their mass: about
+// 7.4 disk scale lengths, 3.8 scale heights and 39 bulge or halo scale lengths out.
+const DISK_MASS_CUTOFF: f64 = 0.995;
+const DISK_HEIGHT_CUTOFF: f64 = 0.999;
+const SPHEROID_MASS_CUTOFF: f64 = 0.95;
+
+// in disk scale lengths
+const DISK_SCALE_HEIGHT: f64 = 0.1;
+const BULGE_SCALE_LENGTH: f64 = 0.2;
+const HALO_SCALE_LENGTH: f64 = 3.0;
+
+// the disk's stability against local collapse, Toomre's Q, is set at this radius and its radial
+// velocity dispersion falls off as sqrt(surface density) from there, as in Hernquist (1993)
+const TOOMRE_Q: f64 = 1.5;
+const TOOMRE_RADIUS: f64 = 2.43;
+
+// bulge and halo bodies faster than this fraction of the local escape speed are redrawn
+const MAX_ESCAPE_FRACTION: f64 = 0.95;
+// the Jeans integrals for the bulge and halo run out to this radius, in midpoint steps
+const JEANS_RADIUS: f64 = 1e4;
+const JEANS_STEPS: usize = 400;
+
+const DISK_COLOR: [f32; 4] = [0.55, 0.7, 1.0, 1.0];
+const BULGE_COLOR: [f32; 4] = [1.0, 0.8, 0.45, 1.0];
+const HALO_COLOR: [f32; 4] = [0.25, 0.2, 0.3, 1.0];
+
+/// A rotating exponential disk in the xy plane, with a Hernquist bulge and dark matter halo, for
+/// `disk_galaxy`.
+///
+/// The disk's scale height is a tenth of its scale length, the bulge's scale length a fifth and the
+/// halo's three times. Either spheroid can be left out by giving it no bodies and no mass.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GalaxyModel {
+    pub disk_mass: f64,
+    pub scale_length: f64,
+    pub disk_bodies: usize,
+    pub bulge_bodies: usize,
+    /// bulge mass over disk mass
+    pub bulge_mass_ratio: f64,
+    pub halo_bodies: usize,
+    /// halo mass over disk mass
+    pub halo_mass_ratio: f64,
+}
+
+impl Default for GalaxyModel {
+    fn default() -> Self {
+        GalaxyModel {
+            disk_mass: 1.0,
+            scale_length: 1.0,
+            disk_bodies: 4000,
+            bulge_bodies: 1000,
+            bulge_mass_ratio: 0.25,
+            halo_bodies: 0,
+            halo_mass_ratio: 0.0,
+        }
+    }
+}
+
+impl GalaxyModel {
+    fn validate(&self) -> Result<()> {
+        ensure!(self.disk_mass > 0.0 && self.disk_mass.is_finite(), "disk mass must be positive, not {}", self.disk_mass);
+        ensure!(self.scale_length > 0.0 && self.scale_length.is_finite(), "scale length must be positive, not {}", self.scale_length);
+        ensure!(self.disk_bodies > 0, "a disk galaxy needs at least one disk body");
+
+        for (name, bodies, ratio) in [
+            ("bulge", self.bulge_bodies, self.bulge_mass_ratio),
+            ("halo", self.halo_bodies, self.halo_mass_ratio),
+        ] {
+            ensure!(ratio >= 0.0 && ratio.is_finite(), "{} mass ratio must be zero or positive, not {}", name, ratio);
+            ensure!((bodies == 0) == (ratio == 0.0), "the {} has {} bodies but a mass ratio of {}", name, bodies, ratio);
+        }
+
+        Ok(())
+    }
+}
+
+// a spherical Hernquist (1990) component, with `mass` being what it would have if it weren't cut off
+struct Spheroid {
+    mass: f64,
+    scale_length: f64,
+}
+
+impl Spheroid {
+    fn new(mass: f64, scale_length: f64) -> Self {
+        Spheroid { mass: mass / SPHEROID_MASS_CUTOFF, scale_length }
+    }
+
+    fn density(&self, r: f64) -> f64 {
+        let a = self.scale_length;
+        self.mass * a / (2.0 * PI * r * (r + a).powi(3))
+    }
+
+    fn enclosed_mass(&self, r: f64) -> f64 {
+        let fraction = (r / (r + self.scale_length)).powi(2);
+        self.mass * fraction.min(SPHEROID_MASS_CUTOFF)
+    }
+
+    fn sample_radius(&self, rng: &mut Rng) -> f64 {
+        // invert M(r) = M r^2 / (r + a)^2, staying off r = 0 where the density diverges
+        let root = (SPHEROID_MASS_CUTOFF * (1.0 - rng.uniform())).sqrt();
+        self.scale_length * root / (1.0 - root)
+    }
+}
+
+// Polynomial approximations to the modified Bessel functions I0, I1, K0 and K1, accurate to about
+// 1e-7 (Abramowitz & Stegun 9.8.1 to 9.8.8), for the rotation curve of an exponential disk.
+fn polynomial(x: f64, coefficients: &[f64]) -> f64 {
+    coefficients.iter().rev().fold(0.0, |sum, c| sum * x + c)
+}
+
+fn bessel_i0(x: f64) -> f64 {
+    let t = x / 3.75;
+    if x <= 3.75 {
+        polynomial(t * t, &[1.0, 3.5156229, 3.0899424, 1.2067492, 0.2659732, 0.0360768, 0.0045813])
+    } else {
+        x.exp() / x.sqrt() * polynomial(1.0 / t, &[
+            0.39894228, 0.01328592, 0.00225319, -0.00157565, 0.00916281, -0.02057706, 0.02635537, -0.01647633, 0.00392377,
+        ])
+    }
+}
+
+fn bessel_i1(x: f64) -> f64 {
+    let t = x / 3.75;
+    if x <= 3.75 {
+        x * polynomial(t * t, &[0.5, 0.87890594, 0.51498869, 0.15084934, 0.02658733, 0.00301532, 0.00032411])
+    } else {
+        x.exp() / x.sqrt() * polynomial(1.0 / t, &[
+            0.39894228, -0.03988024, -0.00362018, 0.00163801, -0.01031555, 0.02282967, -0.02895312, 0.01787654, -0.00420059,
+        ])
+    }
+}
+
+fn bessel_k0(x: f64) -> f64 {
+    if x <= 2.0 {
+        let t = 0.25 * x * x;
+        -(0.5 * x).ln() * bessel_i0(x)
+            + polynomial(t, &[-0.57721566, 0.42278420, 0.23069756, 0.03488590, 0.00262698, 0.00010750, 0.00000740])
+    } else {
+        (-x).exp() / x.sqrt() * polynomial(2.0 / x, &[
+            1.25331414, -0.07832358, 0.02189568, -0.01062446, 0.00587872, -0.00251540, 0.00053208,
+        ])
+    }
+}
+
+fn bessel_k1(x: f64) -> f64 {
+    if x <= 2.0 {
+        let t = 0.25 * x * x;
+        (x * (0.5 * x).ln() * bessel_i1(x)
+            + polynomial(t, &[1.0, 0.15443144, -0.67278579, -0.18156897, -0.01919402, -0.00110404, -0.00004686]))
+            / x
+    } else {
+        (-x).exp() / x.sqrt() * polynomial(2.0 / x, &[
+            1.25331414, 0.23498619, -0.03655620, 0.01504268, -0.00780353, 0.00325614, -0.00068245,
+        ])
+    }
+}
+
+// mass distribution of a galaxy in internal units, where the disk's scale length is 1 and, but for
+// being cut off, its mass would be too
+struct Galaxy {
+    disk_mass: f64,
+    bulge: Spheroid,
+    halo: Spheroid,
+}
+
+impl Galaxy {
+    fn surface_density(&self, radius: f64) -> f64 {
+        self.disk_mass / (2.0 * PI) * (-radius).exp()
+    }
+
+    // mass within `r` with the disk smeared into a sphere, which is how the bulge and halo see it
+    fn enclosed_mass(&self, r: f64) -> f64 {
+        let disk = self.disk_mass * (1.0 - (1.0 + r) * (-r).exp()).min(DISK_MASS_CUTOFF);
+        disk + self.bulge.enclosed_mass(r) + self.halo.enclosed_mass(r)
+    }
+
+    fn circular_speed_squared(&self, radius: f64) -> f64 {
+        // the thin exponential disk's own rotation curve (Freeman 1970)
+        let y = 0.5 * radius;
+        let disk = 2.0 * self.disk_mass * y * y * (bessel_i0(y) * bessel_k0(y) - bessel_i1(y) * bessel_k1(y));
+
+        disk + (self.bulge.enclosed_mass(radius) + self.halo.enclosed_mass(radius)) / radius
+    }
+
+    // squared epicyclic and angular frequencies at `radius`
+    fn frequencies_squared(&self, radius: f64) -> (f64, f64) {
+        let h = 1e-4 * radius;
+        let speed_squared = self.circular_speed_squared(radius);
+        let slope = (self.circular_speed_squared(radius + h) - self.circular_speed_squared(radius - h)) / (2.0 * h);
+
+        (slope / radius + 2.0 * speed_squared / (radius * radius), speed_squared / (radius * radius))
+    }
+
+    // Squared isotropic velocity dispersion of `spheroid` at radius `r` from the Jeans equation, and
+    // the squared escape speed there.
+    fn jeans(&self, spheroid: &Spheroid, r: f64) -> (f64, f64) {
+        let step = (JEANS_RADIUS / r).ln() / JEANS_STEPS as f64;
+        let mut pressure = 0.0;
+        let mut potential = self.enclosed_mass(JEANS_RADIUS) / JEANS_RADIUS;
+
+        for i in 0..JEANS_STEPS {
+            let x = r * ((i as f64 + 0.5) * step).exp();
+            // gravity at x times dx, in steps of ln x
+            let pull = self.enclosed_mass(x) / x * step;
+            pressure += spheroid.density(x) * pull;
+            potential += pull;
+        }
+
+        (pressure / spheroid.density(r), 2.0 * potential)
+    }
+
+    fn disk_body(&self, rng: &mut Rng) -> (cgmath::Vector3<f64>, cgmath::Vector3<f64>) {
+        // invert M(R) = 1 - (1 + R) exp(-R), whose slope R exp(-R) is zero at the center
+        let fraction = DISK_MASS_CUTOFF * (1.0 - rng.uniform());
+        let max_radius = 2.0 * (1.0 - DISK_MASS_CUTOFF).ln().abs();
+        let radius = kepler::safeguarded_newton(
+            |radius| (1.0 - (1.0 + radius) * (-radius).exp() - fraction, radius * (-radius).exp()),
+            1.0,
+            (0.0, max_radius),
+        );
+        let height = DISK_SCALE_HEIGHT * (DISK_HEIGHT_CUTOFF * (2.0 * rng.uniform() - 1.0)).atanh();
+
+        // an isothermal sheet with a sech^2 profile
+        let vertical_dispersion = (PI * self.surface_density(radius) * DISK_SCALE_HEIGHT).sqrt();
+
+        let (kappa_squared, omega_squared) = self.frequencies_squared(radius);
+        let (reference_kappa_squared, _) = self.frequencies_squared(TOOMRE_RADIUS);
+        let radial_dispersion = TOOMRE_Q * 3.36 * self.surface_density(TOOMRE_RADIUS) / reference_kappa_squared.sqrt()
+            * (-0.5 * (radius - TOOMRE_RADIUS)).exp();
+        // the epicyclic approximation, and the asymmetric drift that slows the mean rotation
+        let epicyclic_ratio = kappa_squared / (4.0 * omega_squared);
+        let azimuthal_dispersion = radial_dispersion * epicyclic_ratio.sqrt();
+        let mean_rotation = (self.circular_speed_squared(radius)
+            + radial_dispersion * radial_dispersion * (1.0 - epicyclic_ratio - 2.0 * radius))
+            .max(0.0)
+            .sqrt();
+
+        let phi = 2.0 * PI * rng.uniform();
+        let (sin, cos) = phi.sin_cos();
+        let radial_speed = radial_dispersion * rng.gaussian();
+        let azimuthal_speed = mean_rotation + azimuthal_dispersion * rng.gaussian();
+
+        (
+            cgmath::vec3(radius * cos, radius * sin, height),
+            cgmath::vec3(
+                radial_speed * cos - azimuthal_speed * sin,
+                radial_speed * sin + azimuthal_speed * cos,
+                vertical_dispersion * rng.gaussian(),
+            ),
+        )
+    }
+
+    fn spheroid_body(&self, spheroid: &Spheroid, rng: &mut Rng) -> (cgmath::Vector3<f64>, cgmath::Vector3<f64>) {
+        let r = spheroid.sample_radius(rng);
+        let (dispersion_squared, escape_speed_squared) = self.jeans(spheroid, r);
+        let max_speed_squared = MAX_ESCAPE_FRACTION * MAX_ESCAPE_FRACTION * escape_speed_squared;
+
+        let velocity = loop {
+            let velocity = dispersion_squared.sqrt() * rng.gaussian_vector();
+            if velocity.magnitude2() < max_speed_squared {
+                break velocity;
+            }
+        };
+
+        (r * rng.unit_vector(), velocity)
+    }
+}
+
+// `count` bodies drawn from `sample`, every second one the mirror image of the one before through the
+// center, so that sampling noise in a sparse, extended halo doesn't pull it away from the disk
+fn mirrored_bodies(
+    count: usize,
+    mass: f64,
+    color: [f32; 4],
+    mut sample: impl FnMut() -> (cgmath::Vector3<f64>, cgmath::Vector3<f64>),
+) -> Vec<Body> {
+    let mut bodies: Vec<Body> = Vec::with_capacity(count);
+
+    for i in 0..count {
+        let (position, velocity) = if i % 2 == 0 {
+            sample()
+        } else {
+            (-bodies[i - 1].position, -bodies[i - 1].velocity)
+        };
+
+        let mut body = Body::new(position, velocity, mass);
+        body.color = color;
+        bodies.push(body);
+    }
+
+    bodies
+}
+
+/// A disk galaxy with the disk rotating counter-clockwise in the xy plane, its barycenter at rest at
+/// the origin, and its velocities set for equilibrium under the given gravitational constant.
+///
+/// The disk follows its exact rotation curve, less the asymmetric drift, with dispersions from the
+/// epicyclic approximation. The bulge and halo are isotropic, with dispersions from the Jeans
+/// equation treating the disk as spherical, so the whole is close to but not quite in equilibrium:
+/// expect the disk to thicken a little as it settles.
+pub fn disk_galaxy(model: &GalaxyModel, gravitational_constant: f64, seed: u64) -> Result<Vec<Body>> {
+    model.validate()?;
+    ensure!(gravitational_constant > 0.0, "gravitational constant must be positive, not {}", gravitational_constant);
+
+    let galaxy = Galaxy {
+        disk_mass: 1.0 / DISK_MASS_CUTOFF,
+        bulge: Spheroid::new(model.bulge_mass_ratio, BULGE_SCALE_LENGTH),
+        halo: Spheroid::new(model.halo_mass_ratio, HALO_SCALE_LENGTH),
+    };
+    let mut rng = Rng::new(seed);
+
+    let mut bodies = mirrored_bodies(model.disk_bodies, 1.0 / model.disk_bodies as f64, DISK_COLOR, || galaxy.disk_body(&mut rng));
+    bodies.extend(mirrored_bodies(
+        model.bulge_bodies,
+        model.bulge_mass_ratio / model.bulge_bodies as f64,
+        BULGE_COLOR,
+        || galaxy.spheroid_body(&galaxy.bulge, &mut rng),
+    ));
+    bodies.extend(mirrored_bodies(
+        model.halo_bodies,
+        model.halo_mass_ratio / model.halo_bodies as f64,
+        HALO_COLOR,
+        || galaxy.spheroid_body(&galaxy.halo, &mut rng),
+    ));
+
+    move_to_barycenter(&mut bodies);
+
+    let velocity_scale = (gravitational_constant * model.disk_mass / model.scale_length).sqrt();
+    for body in bodies.iter_mut() {
+        body.position *= model.scale_length;
+        body.velocity *= velocity_scale;
+        body.mass *= model.disk_mass;
+    }
+
+    Ok(bodies)
+}
+
+/// Turns `bodies` about the origin so that their z axis points along `axis`, then moves them to
+/// `position` and sets them moving at `velocity`, for instance to send a generated galaxy towards
+/// another.
+pub fn place_bodies(
+    bodies: &mut [Body],
+    axis: cgmath::Vector3<f64>,
+    position: cgmath::Vector3<f64>,
+    velocity: cgmath::Vector3<f64>,
+) {
+    let rotation = cgmath::Quaternion::from_arc(cgmath::Vector3::unit_z(), axis.normalize(), Some(cgmath::Vector3::unit_x()));
+
+    for body in bodies.iter_mut() {
+        body.position = position + rotation * body.position;
+        body.velocity = velocity + rotation * body.velocity;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::{Leapfrog, Simulation, UnitSystem};
+
+    const N: usize = 4000;
+
+    // the radii enclosing each of `fractions` of the bodies
+    fn quantile_radii(bodies: &[Body], fractions: &[f64]) -> Vec<f64> {
+        let mut radii: Vec<f64> = bodies.iter().map(|b| b.position.magnitude()).collect();
+        radii.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        fractions.iter().map(|fraction| radii[(fraction * radii.len() as f64) as usize]).collect()
+    }
+
+    fn assert_in_nbody_units(bodies: Vec<Body>) {
+        let simulation = Simulation::from_bodies(bodies, Box::new(Leapfrog), UnitSystem::NBody);
+        let diagnostics = simulation.diagnostics();
+
+        assert!((diagnostics.kinetic_energy + diagnostics.potential_energy + 0.25).abs() < 1e-12);
+        // virial ratio Q = T / |W|
+        assert!((diagnostics.kinetic_energy / -diagnostics.potential_energy - 0.5).abs() < 1e-12);
+        assert!(diagnostics.linear_momentum.magnitude() < 1e-14);
+        assert!(simulation.barycenter().magnitude() < 1e-14);
+    }
+
+    #[test]
+    fn plummer_spheres_follow_the_plummer_mass_profile() {
+        let bodies = plummer_sphere(N, 42).unwrap();
+
+        // the scale radius in N-body units; the cutoff changes the energy too little to matter here
+        let scale_radius = 3.0 * PI / 16.0;
+        let fractions = [0.1, 0.25, 0.5, 0.75, 0.9];
+        let radii = quantile_radii(&bodies, &fractions);
+
+        for (fraction, radius) in fractions.iter().zip(radii) {
+            // M(r) = r^3 / (r^2 + a^2)^(3/2), out of the PLUMMER_MASS_CUTOFF that is sampled
+            let expected = scale_radius / ((fraction * PLUMMER_MASS_CUTOFF).powf(-2.0 / 3.0) - 1.0).sqrt();
+            assert!((radius / expected - 1.0).abs() < 0.05, "{} of the mass within {} rather than {}", fraction, radius, expected);
+        }
+
+        assert_in_nbody_units(bodies);
+    }
+
+    #[test]
+    fn king_models_follow_the_king_mass_profile() {
+        for w0 in [3.0, 7.0] {
+            let bodies = king_model(N, w0, 42).unwrap();
+
+            // the King radius in N-body units isn't known up front, so compare radii relative to the half-mass one
+            let profile = king_profile(w0);
+            let total_mass = profile.last().unwrap().2;
+            let analytic_radius = |fraction: f64| {
+                let i = profile.partition_point(|&(_, _, mass)| mass < fraction * total_mass);
+                profile[i].0
+            };
+
+            let fractions = [0.1, 0.25, 0.75, 0.9];
+            let radii = quantile_radii(&bodies, &fractions);
+            let half_mass_radius = quantile_radii(&bodies, &[0.5])[0];
+
+            for (fraction, radius) in fractions.iter().zip(radii) {
+                let expected = analytic_radius(*fraction) / analytic_radius(0.5);
+                let actual = radius / half_mass_radius;
+                assert!((actual / expected - 1.0).abs() < 0.05, "W0 = {}: {} of the mass within {} half-mass radii rather than {}", w0, fraction, actual, expected);
+            }
+
+            // nothing beyond the tidal radius
+            let tidal_radius = profile.last().unwrap().0 / analytic_radius(0.5) * half_mass_radius;
+            assert!(bodies.iter().all(|b| b.position.magnitude() <= 1.05 * tidal_radius));
+
+            assert_in_nbody_units(bodies);
+        }
+    }
+}
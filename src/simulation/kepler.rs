@@ -0,0 +1,291 @@
+// analytic two-body motion: Kepler's equation and universal-variable propagation
+//
+// Besides moving bodies that only feel a single primary, these are the exact solution the
+// integrators can be checked against.
+
+use cgmath::prelude::*;
+use std::f64::consts::PI;
+
+const MAX_ITERATIONS: usize = 100;
+
+/// Solves Kepler's equation for the anomaly matching `mean_anomaly`, in the same form
+/// `OrbitalElements::eccentric_anomaly` reports it: the eccentric anomaly E for elliptic orbits
+/// (M = E - e sin E), the hyperbolic anomaly H for hyperbolic ones (M = e sinh H - H) and
+/// D = tan(true anomaly / 2) for parabolic ones (M = D + D^3 / 3).
+pub fn solve_kepler(mean_anomaly: f64, eccentricity: f64) -> f64 {
+    let (m, e) = (mean_anomaly, eccentricity);
+
+    if e < 1.0 {
+        // solve for M reduced to [-pi, pi], where E lies within e of M
+        let turns = (m / (2.0 * PI)).round() * 2.0 * PI;
+        let reduced = m - turns;
+        let guess = reduced + 0.85 * e * reduced.signum();
+
+        turns + safeguarded_newton(
+            |anomaly| (anomaly - e * anomaly.sin() - reduced, 1.0 - e * anomaly.cos()),
+            guess,
+            (reduced - e, reduced + e),
+        )
+    } else if e > 1.0 {
+        // e sinh H - H grows monotonically, and |M| / e <= sinh |H| <= |M| / (e - 1)
+        let bounds = ((m.abs() / e).asinh(), (m.abs() / (e - 1.0)).asinh());
+        let (low, high) = if m < 0.0 { (-bounds.1, -bounds.0) } else { bounds };
+        let guess = (2.0 * m / e).asinh().clamp(low, high);
+
+        safeguarded_newton(|anomaly| (e * anomaly.sinh() - anomaly - m, e * anomaly.cosh() - 1.0), guess, (low, high))
+    } else {
+        // Barker's equation is a cubic with a single real root
+        2.0 * ((1.5 * m).asinh() / 3.0).sinh()
+    }
+}
+
+/// True anomaly at `mean_anomaly` on an orbit with the given eccentricity.
+pub fn true_anomaly_from_mean(mean_anomaly: f64, eccentricity: f64) -> f64 {
+    let e = eccentricity;
+    let anomaly = solve_kepler(mean_anomaly, e);
+
+    if e < 1.0 {
+        let (sin, cos) = anomaly.sin_cos();
+        ((1.0 - e * e).sqrt() * sin).atan2(cos - e).rem_euclid(2.0 * PI)
+    } else if e > 1.0 {
+        2.0 * (((e + 1.0) / (e - 1.0)).sqrt() * (0.5 * anomaly).tanh()).atan()
+    } else {
+        2.0 * anomaly.atan()
+    }
+}
+
+// Newton's method on an increasing function, falling back to bisection whenever a step would leave
+// the bracket `(low, high)` around the root. `f` returns the function and its derivative.
+pub(super) fn safeguarded_newton(f: impl Fn(f64) -> (f64, f64), guess: f64, (mut low, mut high): (f64, f64)) -> f64 {
+    let mut x = guess.clamp(low, high);
+
+    for _ in 0..MAX_ITERATIONS {
+        let (value, derivative) = f(x);
+
+        if value == 0.0 {
+            return x;
+        } else if value < 0.0 {
+            low = x;
+        } else {
+            high = x;
+        }
+
+        let step = x - value / derivative;
+        let next = if step > low && step < high { step } else { 0.5 * (low + high) };
+
+        if (next - x).abs() <= 4.0 * f64::EPSILON * next.abs().max(1.0) {
+            return next;
+        }
+
+        x = next;
+    }
+
+    x
+}
+
+// Stumpff functions C(z) = (1 - cos sqrt z) / z and S(z) = (sqrt z - sin sqrt z) / sqrt(z)^3,
+// continued to negative z through cosh and sinh
+fn stumpff(z: f64) -> (f64, f64) {
+    if z.abs() < 1e-2 {
+        // the closed forms lose precision to cancellation near zero
+        let c = 1.0 / 2.0 - z / 24.0 + z * z / 720.0 - z.powi(3) / 40320.0 + z.powi(4) / 3628800.0;
+        let s = 1.0 / 6.0 - z / 120.0 + z * z / 5040.0 - z.powi(3) / 362880.0 + z.powi(4) / 39916800.0;
+        (c, s)
+    } else if z > 0.0 {
+        let root = z.sqrt();
+        ((1.0 - root.cos()) / z, (root - root.sin()) / (root * z))
+    } else {
+        let root = (-z).sqrt();
+        ((root.cosh() - 1.0) / -z, (root.sinh() - root) / (root * -z))
+    }
+}
+
+/// Relative position and velocity after `dt` on the two-body orbit starting from `position` and
+/// `velocity` around a primary with gravitational parameter `mu`.
+///
+/// Uses universal variables, so elliptic, parabolic and hyperbolic orbits are all handled alike,
+/// and `dt` may be negative.
+pub fn propagate_kepler(
+    position: cgmath::Vector3<f64>,
+    velocity: cgmath::Vector3<f64>,
+    mu: f64,
+    dt: f64,
+) -> (cgmath::Vector3<f64>, cgmath::Vector3<f64>) {
+    let r0 = position.magnitude();
+    let sqrt_mu = mu.sqrt();
+    let sigma = position.dot(velocity) / sqrt_mu;
+    // reciprocal of the semi-major axis: positive when bound, zero when parabolic
+    let alpha = 2.0 / r0 - velocity.magnitude2() / mu;
+
+    // whole revolutions don't change the state, and dropping them keeps the iteration short
+    let dt = if alpha > 0.0 {
+        let period = 2.0 * PI / (sqrt_mu * alpha.powf(1.5));
+        dt - (dt / period).trunc() * period
+    } else {
+        dt
+    };
+
+    // Kepler's equation in the universal anomaly chi, and its first two derivatives
+    let equation = |chi: f64| {
+        let z = alpha * chi * chi;
+        let (c, s) = stumpff(z);
+        let value = sigma * chi * chi * c + (1.0 - alpha * r0) * chi.powi(3) * s + r0 * chi - sqrt_mu * dt;
+        let derivative = sigma * chi * (1.0 - z * s) + (1.0 - alpha * r0) * chi * chi * c + r0;
+        let second_derivative = sigma * (1.0 - z * c) + (1.0 - alpha * r0) * chi * (1.0 - z * s);
+        (value, derivative, second_derivative)
+    };
+
+    // Laguerre's method, which converges from a rough guess on every kind of orbit
+    const ORDER: f64 = 5.0;
+    let mut chi = if alpha > 0.0 {
+        sqrt_mu * alpha * dt
+    } else {
+        // for hyperbolic orbits the linear guess overshoots badly, sending cosh out of range,
+        // so start from the asymptotic logarithmic estimate instead (Vallado, Algorithm 8)
+        let a = 1.0 / alpha;
+        let estimate = dt.signum() * (-a).sqrt()
+            * ((-2.0 * mu * alpha * dt) / (sigma * sqrt_mu + dt.signum() * (-mu * a).sqrt() * (1.0 - r0 * alpha))).ln();
+
+        if estimate.is_finite() { estimate } else { sqrt_mu * dt / r0 }
+    };
+
+    for _ in 0..MAX_ITERATIONS {
+        let (value, derivative, second_derivative) = equation(chi);
+        let discriminant = ((ORDER - 1.0).powi(2) * derivative * derivative
+            - ORDER * (ORDER - 1.0) * value * second_derivative)
+            .abs()
+            .sqrt();
+        let step = ORDER * value / (derivative + derivative.signum() * discriminant);
+        chi -= step;
+
+        if step.abs() <= 4.0 * f64::EPSILON * chi.abs().max(1.0) {
+            break;
+        }
+    }
+
+    let z = alpha * chi * chi;
+    let (c, s) = stumpff(z);
+    let f = 1.0 - chi * chi / r0 * c;
+    let g = dt - chi.powi(3) / sqrt_mu * s;
+    let new_position = f * position + g * velocity;
+
+    let r = new_position.magnitude();
+    let f_dot = sqrt_mu / (r * r0) * (z * s - 1.0) * chi;
+    let g_dot = 1.0 - chi * chi / r * c;
+    let new_velocity = f_dot * position + g_dot * velocity;
+
+    (new_position, new_velocity)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Kepler's equation, in whichever form `solve_kepler` solves for `eccentricity`
+    fn kepler_residual(mean_anomaly: f64, eccentricity: f64) -> f64 {
+        let (anomaly, e) = (solve_kepler(mean_anomaly, eccentricity), eccentricity);
+
+        let mean = if e < 1.0 {
+            anomaly - e * anomaly.sin()
+        } else if e > 1.0 {
+            e * anomaly.sinh() - anomaly
+        } else {
+            anomaly + anomaly.powi(3) / 3.0
+        };
+
+        (mean - mean_anomaly).abs() / mean_anomaly.abs().max(1.0)
+    }
+
+    const MEAN_ANOMALIES: [f64; 9] = [-1e3, -PI, -0.5, -1e-6, 0.0, 1e-6, 0.3, 3.0, 100.0];
+
+    #[test]
+    fn kepler_solutions_approaching_parabolic_from_below() {
+        for e in [0.0, 0.5, 0.9, 0.99, 0.999999, 1.0 - 1e-12] {
+            for m in MEAN_ANOMALIES {
+                assert!(kepler_residual(m, e) < 1e-13, "e = {}, M = {}: residual {:e}", e, m, kepler_residual(m, e));
+            }
+        }
+    }
+
+    #[test]
+    fn barker_solutions_for_parabolic_orbits() {
+        for m in MEAN_ANOMALIES.iter().copied().chain([1e6, -1e9]) {
+            assert!(kepler_residual(m, 1.0) < 1e-13, "M = {}: residual {:e}", m, kepler_residual(m, 1.0));
+        }
+    }
+
+    #[test]
+    fn kepler_solutions_for_hyperbolic_orbits() {
+        for e in [1.0 + 1e-9, 1.001, 1.5, 5.0, 100.0] {
+            for m in MEAN_ANOMALIES.iter().copied().chain([1e6, -1e9]) {
+                assert!(kepler_residual(m, e) < 1e-13, "e = {}, M = {}: residual {:e}", e, m, kepler_residual(m, e));
+            }
+        }
+    }
+
+    const MU: f64 = 2.5;
+
+    fn energy(position: cgmath::Vector3<f64>, velocity: cgmath::Vector3<f64>) -> f64 {
+        0.5 * velocity.magnitude2() - MU / position.magnitude()
+    }
+
+    // propagating by `dt` in one call and in `steps` shorter ones should agree with each other, and
+    // with the energy and angular momentum of the starting state
+    fn assert_consistent_propagation(position: cgmath::Vector3<f64>, velocity: cgmath::Vector3<f64>, dt: f64, steps: usize) {
+        let (end_position, end_velocity) = propagate_kepler(position, velocity, MU, dt);
+
+        let (mut stepped_position, mut stepped_velocity) = (position, velocity);
+        for _ in 0..steps {
+            let state = propagate_kepler(stepped_position, stepped_velocity, MU, dt / steps as f64);
+            stepped_position = state.0;
+            stepped_velocity = state.1;
+        }
+
+        let scale = end_position.magnitude();
+        assert!((stepped_position - end_position).magnitude() < 1e-9 * scale, "positions {:?} and {:?}", stepped_position, end_position);
+        assert!((stepped_velocity - end_velocity).magnitude() < 1e-9 * end_velocity.magnitude());
+
+        let (initial_energy, final_energy) = (energy(position, velocity), energy(end_position, end_velocity));
+        assert!((final_energy - initial_energy).abs() < 1e-10 * (MU / position.magnitude()), "energy {} became {}", initial_energy, final_energy);
+
+        let (initial_momentum, final_momentum) = (position.cross(velocity), end_position.cross(end_velocity));
+        assert!((final_momentum - initial_momentum).magnitude() < 1e-10 * initial_momentum.magnitude());
+    }
+
+    #[test]
+    fn propagating_elliptic_orbits_forwards_and_backwards() {
+        let position = cgmath::vec3(1.0, 0.2, -0.1);
+        let velocity = cgmath::vec3(-0.3, 1.1, 0.4);
+
+        assert_consistent_propagation(position, velocity, 7.3, 10);
+        assert_consistent_propagation(position, velocity, -7.3, 10);
+
+        // and back to where it started
+        let (there_position, there_velocity) = propagate_kepler(position, velocity, MU, 5.0);
+        let (back_position, back_velocity) = propagate_kepler(there_position, there_velocity, MU, -5.0);
+        assert!((back_position - position).magnitude() < 1e-12);
+        assert!((back_velocity - velocity).magnitude() < 1e-12);
+    }
+
+    #[test]
+    fn propagating_hyperbolic_orbits() {
+        let position = cgmath::vec3(1.0, 0.0, 0.0);
+        let velocity = cgmath::vec3(0.5, 3.0, 0.2);
+        assert!(energy(position, velocity) > 0.0);
+
+        assert_consistent_propagation(position, velocity, 20.0, 10);
+        assert_consistent_propagation(position, velocity, -20.0, 10);
+    }
+
+    #[test]
+    fn propagating_near_parabolic_orbits() {
+        let position = cgmath::vec3(0.0, 2.0, 0.0);
+        let escape_speed = (2.0 * MU / 2.0_f64).sqrt();
+
+        for factor in [1.0 - 1e-9, 1.0, 1.0 + 1e-9] {
+            let velocity = cgmath::vec3(-1.0, 0.3, 0.0).normalize() * (escape_speed * factor);
+            assert_consistent_propagation(position, velocity, 15.0, 10);
+            assert_consistent_propagation(position, velocity, -15.0, 10);
+        }
+    }
+}
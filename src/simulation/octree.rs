@@ -0,0 +1,223 @@
+// Barnes-Hut octree for approximate gravitational accelerations in O(N log N)
+
+use cgmath::prelude::*;
+
+use super::summation::VectorSum;
+use super::{point_mass_acceleration, Body, ForceModel, Scalar};
+
+// past this depth, bodies that still share a cell (e.g. coincident ones) are kept together in one leaf
+const MAX_DEPTH: u32 = 32;
+
+enum NodeKind {
+    Empty,
+    Leaf(Vec<usize>),
+    Internal([usize; 8]),
+}
+
+struct Node<S> {
+    center: cgmath::Vector3<S>,
+    half_width: S,
+    mass: S,
+    // mass-weighted position sum while building, center of mass once built
+    center_of_mass: cgmath::Vector3<S>,
+    kind: NodeKind,
+}
+
+impl<S: Scalar> Node<S> {
+    fn new(center: cgmath::Vector3<S>, half_width: S) -> Self {
+        Node {
+            center,
+            half_width,
+            mass: S::zero(),
+            center_of_mass: cgmath::Vector3::zero(),
+            kind: NodeKind::Empty,
+        }
+    }
+
+    fn octant(&self, position: cgmath::Vector3<S>) -> usize {
+        (position.x >= self.center.x) as usize
+            | ((position.y >= self.center.y) as usize) << 1
+            | ((position.z >= self.center.z) as usize) << 2
+    }
+
+    fn contains(&self, position: cgmath::Vector3<S>) -> bool {
+        let offset = position - self.center;
+        offset.x.abs() <= self.half_width && offset.y.abs() <= self.half_width && offset.z.abs() <= self.half_width
+    }
+}
+
+pub struct Octree<'a, S = f64> {
+    bodies: &'a [Body<S>],
+    nodes: Vec<Node<S>>,
+}
+
+impl<'a, S: Scalar> Octree<'a, S> {
+    pub fn new(bodies: &'a [Body<S>]) -> Self {
+        let mut tree = Octree { bodies, nodes: Vec::new() };
+
+        if bodies.is_empty() {
+            return tree;
+        }
+
+        let (min, max) = bodies.iter().fold(
+            (bodies[0].position, bodies[0].position),
+            |(min, max), b| {
+                (
+                    cgmath::vec3(min.x.min(b.position.x), min.y.min(b.position.y), min.z.min(b.position.z)),
+                    cgmath::vec3(max.x.max(b.position.x), max.y.max(b.position.y), max.z.max(b.position.z)),
+                )
+            },
+        );
+
+        let extent = max - min;
+        let half = S::from_f64(0.5);
+        let half_width = half * extent.x.max(extent.y).max(extent.z).max(S::min_positive_value()) * S::from_f64(1.0 + 1e-9);
+        tree.nodes.push(Node::new((min + max) * half, half_width));
+
+        for i in 0..bodies.len() {
+            tree.insert(0, i, 0);
+        }
+
+        for node in &mut tree.nodes {
+            if node.mass > S::zero() {
+                node.center_of_mass /= node.mass;
+            }
+        }
+
+        tree
+    }
+
+    fn insert(&mut self, node_index: usize, body_index: usize, depth: u32) {
+        let body = &self.bodies[body_index];
+        let node = &mut self.nodes[node_index];
+        node.mass += body.mass;
+        node.center_of_mass += body.position * body.mass;
+        let octant = node.octant(body.position);
+
+        match &mut node.kind {
+            NodeKind::Empty => node.kind = NodeKind::Leaf(vec![body_index]),
+            NodeKind::Leaf(contents) if depth >= MAX_DEPTH => contents.push(body_index),
+            NodeKind::Leaf(_) => {
+                let contents = match std::mem::replace(&mut node.kind, NodeKind::Empty) {
+                    NodeKind::Leaf(contents) => contents,
+                    _ => unreachable!(),
+                };
+
+                let children = self.subdivide(node_index);
+                self.nodes[node_index].kind = NodeKind::Internal(children);
+
+                for existing in contents {
+                    let octant = self.nodes[node_index].octant(self.bodies[existing].position);
+                    self.insert(children[octant], existing, depth + 1);
+                }
+
+                self.insert(children[octant], body_index, depth + 1);
+            }
+            NodeKind::Internal(children) => {
+                let child = children[octant];
+                self.insert(child, body_index, depth + 1);
+            }
+        }
+    }
+
+    fn subdivide(&mut self, node_index: usize) -> [usize; 8] {
+        let center = self.nodes[node_index].center;
+        let quarter_width = S::from_f64(0.5) * self.nodes[node_index].half_width;
+        let mut children = [0; 8];
+
+        for (octant, child) in children.iter_mut().enumerate() {
+            let offset = cgmath::vec3(
+                if octant & 1 != 0 { quarter_width } else { -quarter_width },
+                if octant & 2 != 0 { quarter_width } else { -quarter_width },
+                if octant & 4 != 0 { quarter_width } else { -quarter_width },
+            );
+
+            *child = self.nodes.len();
+            self.nodes.push(Node::new(center + offset, quarter_width));
+        }
+
+        children
+    }
+
+    /// Acceleration on body `index`. Cells are treated as point masses once their width
+    /// over their distance drops below `theta`; `theta = 0` degenerates to direct summation.
+    pub fn acceleration(&self, index: usize, theta: f64, forces: &ForceModel<S>) -> cgmath::Vector3<S> {
+        self.acceleration_at(self.bodies[index].position, Some(index), theta, forces)
+    }
+
+    /// Acceleration at an arbitrary `position`, leaving out body `exclude` if given.
+    pub fn acceleration_at(
+        &self,
+        position: cgmath::Vector3<S>,
+        exclude: Option<usize>,
+        theta: f64,
+        forces: &ForceModel<S>,
+    ) -> cgmath::Vector3<S> {
+        let (two, theta) = (S::from_f64(2.0), S::from_f64(theta));
+        let mut acceleration = VectorSum::new(forces.summation);
+
+        if self.nodes.is_empty() {
+            return acceleration.value();
+        }
+
+        let mut stack = vec![0];
+
+        while let Some(node_index) = stack.pop() {
+            let node = &self.nodes[node_index];
+
+            match &node.kind {
+                NodeKind::Empty => {}
+                NodeKind::Leaf(contents) => {
+                    for &other in contents {
+                        if Some(other) != exclude {
+                            let other = &self.bodies[other];
+                            acceleration.add(point_mass_acceleration(position, other.position, other.mass, forces));
+                        }
+                    }
+                }
+                NodeKind::Internal(children) => {
+                    let distance = (node.center_of_mass - position).magnitude();
+
+                    // never approximate the cell the position itself sits in
+                    if !node.contains(position) && two * node.half_width < theta * distance {
+                        acceleration.add(point_mass_acceleration(position, node.center_of_mass, node.mass, forces));
+                    } else {
+                        stack.extend_from_slice(children);
+                    }
+                }
+            }
+        }
+
+        acceleration.value()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::{direct_acceleration, plummer_sphere, ForceSolver, Parallelism, Softening, Summation};
+
+    #[test]
+    fn barnes_hut_is_close_to_direct_summation() {
+        let theta = 0.5;
+        let bodies = plummer_sphere(1000, 7).unwrap();
+        let forces = ForceModel {
+            gravitational_constant: 1.0,
+            solver: ForceSolver::BarnesHut { theta },
+            parallelism: Parallelism::Sequential,
+            softening: Softening::None,
+            summation: Summation::Naive,
+        };
+        let tree = Octree::new(&bodies);
+
+        let mut errors: Vec<f64> = (0..bodies.len()).map(|i| {
+            let exact = direct_acceleration(&bodies, i, &forces);
+            (tree.acceleration(i, theta, &forces) - exact).magnitude() / exact.magnitude()
+        }).collect();
+        errors.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        let (median, max) = (errors[errors.len() / 2], errors[errors.len() - 1]);
+        assert!(median < 5e-3, "median relative error {:e} at theta = {}", median, theta);
+        assert!(max < 5e-2, "maximum relative error {:e} at theta = {}", max, theta);
+    }
+}
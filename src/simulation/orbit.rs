@@ -0,0 +1,338 @@
+// conversion between state vectors and Keplerian orbital elements
+//
+// Angles are in radians, measured in the xy plane from the x axis. Where an angle is undefined the
+// usual substitutes are used: equatorial orbits have a zero ascending node and measure periapsis from
+// the x axis, and circular orbits have a zero argument of periapsis and measure the true anomaly from
+// the ascending node (or from the x axis, if also equatorial).
+
+use cgmath::prelude::*;
+use std::f64::consts::PI;
+
+use super::{Body, BodyId, Simulation};
+
+// below this, eccentricity and the node vector's relative length are treated as zero
+const TOLERANCE: f64 = 1e-11;
+
+/// What an orbit is measured relative to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Primary {
+    Body(BodyId),
+    /// the barycenter of the whole system, with the rest of it treated as a single mass there
+    Barycenter,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OrbitalElements {
+    /// negative for hyperbolic orbits, infinite for parabolic ones
+    pub semi_major_axis: f64,
+    pub eccentricity: f64,
+    /// 0 to pi, above pi/2 for retrograde orbits
+    pub inclination: f64,
+    pub longitude_of_ascending_node: f64,
+    pub argument_of_periapsis: f64,
+    pub true_anomaly: f64,
+}
+
+fn normalize_angle(angle: f64) -> f64 {
+    let angle = angle.rem_euclid(2.0 * PI);
+    // rem_euclid can round up to exactly 2 pi
+    if angle >= 2.0 * PI { 0.0 } else { angle }
+}
+
+// signed angle from `from` to `to`, measured counter-clockwise around `normal`
+fn angle_around(from: cgmath::Vector3<f64>, to: cgmath::Vector3<f64>, normal: cgmath::Vector3<f64>) -> f64 {
+    normalize_angle(normal.dot(from.cross(to)).atan2(from.dot(to)))
+}
+
+impl OrbitalElements {
+    /// Elements of the orbit with relative `position` and `velocity` around a primary with
+    /// gravitational parameter `mu` (G times the mass of both bodies, for a two-body orbit).
+    pub fn from_state_vectors(position: cgmath::Vector3<f64>, velocity: cgmath::Vector3<f64>, mu: f64) -> Self {
+        let r = position.magnitude();
+        let angular_momentum = position.cross(velocity);
+        let normal = angular_momentum.normalize();
+        let node = cgmath::vec3(-angular_momentum.y, angular_momentum.x, 0.0);
+        let eccentricity_vector = ((velocity.magnitude2() - mu / r) * position - position.dot(velocity) * velocity) / mu;
+        let eccentricity = eccentricity_vector.magnitude();
+        let energy = 0.5 * velocity.magnitude2() - mu / r;
+
+        let equatorial = node.magnitude() <= TOLERANCE * angular_momentum.magnitude();
+        let circular = eccentricity <= TOLERANCE;
+
+        // the direction the remaining angles are measured from
+        let reference = if equatorial { cgmath::Vector3::unit_x() } else { node.normalize() };
+
+        OrbitalElements {
+            semi_major_axis: -mu / (2.0 * energy),
+            eccentricity,
+            inclination: (angular_momentum.z / angular_momentum.magnitude()).clamp(-1.0, 1.0).acos(),
+            longitude_of_ascending_node: if equatorial { 0.0 } else { normalize_angle(node.y.atan2(node.x)) },
+            argument_of_periapsis: if circular { 0.0 } else { angle_around(reference, eccentricity_vector, normal) },
+            true_anomaly: if circular {
+                angle_around(reference, position, normal)
+            } else {
+                angle_around(eccentricity_vector, position, normal)
+            },
+        }
+    }
+
+    /// Relative position and velocity on this orbit around a primary with gravitational parameter `mu`.
+    ///
+    /// Parabolic orbits can't be described by their semi-major axis, so this only works for e != 1.
+    pub fn to_state_vectors(&self, mu: f64) -> (cgmath::Vector3<f64>, cgmath::Vector3<f64>) {
+        let e = self.eccentricity;
+        let (sin, cos) = self.true_anomaly.sin_cos();
+        let semi_latus_rectum = self.semi_major_axis * (1.0 - e * e);
+        let r = semi_latus_rectum / (1.0 + e * cos);
+
+        // in the orbital plane, with x towards periapsis
+        let position = cgmath::vec3(r * cos, r * sin, 0.0);
+        let velocity = (mu / semi_latus_rectum).sqrt() * cgmath::vec3(-sin, e + cos, 0.0);
+
+        let rotation = cgmath::Matrix3::from_angle_z(cgmath::Rad(self.longitude_of_ascending_node))
+            * cgmath::Matrix3::from_angle_x(cgmath::Rad(self.inclination))
+            * cgmath::Matrix3::from_angle_z(cgmath::Rad(self.argument_of_periapsis));
+
+        (rotation * position, rotation * velocity)
+    }
+
+    pub fn is_bound(&self) -> bool {
+        self.eccentricity < 1.0
+    }
+
+    /// NaN for parabolic orbits, whose semi-major axis is infinite.
+    pub fn periapsis(&self) -> f64 {
+        self.semi_major_axis * (1.0 - self.eccentricity)
+    }
+
+    /// Infinite for unbound orbits.
+    pub fn apoapsis(&self) -> f64 {
+        if self.is_bound() {
+            self.semi_major_axis * (1.0 + self.eccentricity)
+        } else {
+            f64::INFINITY
+        }
+    }
+
+    /// Time for one revolution, or `None` for unbound orbits.
+    pub fn period(&self, mu: f64) -> Option<f64> {
+        if self.is_bound() {
+            Some(2.0 * PI * (self.semi_major_axis.powi(3) / mu).sqrt())
+        } else {
+            None
+        }
+    }
+
+    /// Eccentric anomaly for elliptic orbits, hyperbolic anomaly for hyperbolic ones and
+    /// tan(true anomaly / 2) for parabolic ones.
+    pub fn eccentric_anomaly(&self) -> f64 {
+        let e = self.eccentricity;
+        let (sin, cos) = self.true_anomaly.sin_cos();
+
+        if e < 1.0 {
+            normalize_angle(((1.0 - e * e).sqrt() * sin).atan2(e + cos))
+        } else if e > 1.0 {
+            ((e * e - 1.0).sqrt() * sin / (1.0 + e * cos)).asinh()
+        } else {
+            (0.5 * self.true_anomaly).tan()
+        }
+    }
+
+    /// Mean anomaly, in [0, 2 pi) for elliptic orbits and unbounded otherwise.
+    pub fn mean_anomaly(&self) -> f64 {
+        let e = self.eccentricity;
+        let anomaly = self.eccentric_anomaly();
+
+        if e < 1.0 {
+            normalize_angle(anomaly - e * anomaly.sin())
+        } else if e > 1.0 {
+            e * anomaly.sinh() - anomaly
+        } else {
+            // Barker's equation
+            anomaly + anomaly.powi(3) / 3.0
+        }
+    }
+}
+
+impl std::fmt::Display for OrbitalElements {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "a = {:e}, e = {:.6}, i = {:.3}°, Ω = {:.3}°, ω = {:.3}°, ν = {:.3}°",
+            self.semi_major_axis,
+            self.eccentricity,
+            self.inclination.to_degrees(),
+            self.longitude_of_ascending_node.to_degrees(),
+            self.argument_of_periapsis.to_degrees(),
+            self.true_anomaly.to_degrees(),
+        )
+    }
+}
+
+impl Simulation {
+    /// Elements of the orbit body `id` is on around `primary`, if both exist and differ.
+    ///
+    /// Around a body this is the two-body orbit of the pair. Around the barycenter the rest of
+    /// the system is treated as a single mass, which is exact for two bodies.
+    pub fn orbital_elements(&self, id: BodyId, primary: Primary) -> Option<OrbitalElements> {
+        let body = self.body(id)?;
+        let gravitational_constant = self.forces.gravitational_constant;
+
+        let (position, velocity, mu) = match primary {
+            Primary::Body(primary) if primary == id => return None,
+            Primary::Body(primary) => {
+                let primary = self.body(primary)?;
+                (
+                    body.position - primary.position,
+                    body.velocity - primary.velocity,
+                    gravitational_constant * (body.mass + primary.mass),
+                )
+            }
+            Primary::Barycenter => {
+                let bodies = self.bodies();
+                let total_mass: f64 = bodies.iter().map(|b| b.mass).sum();
+                let rest = total_mass - body.mass;
+
+                if rest <= 0.0 {
+                    return None;
+                }
+
+                let momentum: cgmath::Vector3<f64> = bodies.iter().map(|b| b.mass * b.velocity).sum();
+                (
+                    body.position - self.barycenter(),
+                    body.velocity - momentum / total_mass,
+                    // a body at distance r from the barycenter is r * M / rest from the remaining mass
+                    gravitational_constant * rest.powi(3) / (total_mass * total_mass),
+                )
+            }
+        };
+
+        Some(OrbitalElements::from_state_vectors(position, velocity, mu))
+    }
+
+    /// Adds `body` on the orbit around `primary` described by `elements`, keeping its mass, name and
+    /// color but replacing its position and velocity. Returns `None` if `primary` doesn't exist.
+    ///
+    /// Elements are interpreted the same way `orbital_elements` reports them, so the new body's
+    /// orbital elements read back as `elements`.
+    pub fn add_body_on_orbit(&mut self, mut body: Body, primary: Primary, elements: &OrbitalElements) -> Option<BodyId> {
+        let gravitational_constant = self.forces.gravitational_constant;
+
+        let (position, velocity) = match primary {
+            Primary::Body(primary) => {
+                let primary = self.body(primary)?;
+                let (position, velocity) = elements.to_state_vectors(gravitational_constant * (body.mass + primary.mass));
+                (primary.position + position, primary.velocity + velocity)
+            }
+            Primary::Barycenter => {
+                let bodies = self.bodies();
+                let rest: f64 = bodies.iter().map(|b| b.mass).sum();
+
+                if rest <= 0.0 {
+                    return None;
+                }
+
+                let total_mass = rest + body.mass;
+                let center: cgmath::Vector3<f64> = bodies.iter().map(|b| b.mass * b.position).sum::<cgmath::Vector3<f64>>() / rest;
+                let center_velocity: cgmath::Vector3<f64> = bodies.iter().map(|b| b.mass * b.velocity).sum::<cgmath::Vector3<f64>>() / rest;
+                let (position, velocity) = elements.to_state_vectors(gravitational_constant * rest.powi(3) / (total_mass * total_mass));
+
+                // the elements are relative to the barycenter including the new body, which sits
+                // rest / total_mass of the way from it to the rest of the system
+                let scale = total_mass / rest;
+                (center + scale * position, center_velocity + scale * velocity)
+            }
+        };
+
+        body.position = position;
+        body.velocity = velocity;
+        Some(self.insert_body(body))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MU: f64 = 0.3;
+
+    fn elements(semi_major_axis: f64, eccentricity: f64, inclination: f64, node: f64, periapsis: f64, anomaly: f64) -> OrbitalElements {
+        OrbitalElements {
+            semi_major_axis,
+            eccentricity,
+            inclination,
+            longitude_of_ascending_node: node,
+            argument_of_periapsis: periapsis,
+            true_anomaly: anomaly,
+        }
+    }
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64, what: &str) {
+        assert!((actual - expected).abs() <= tolerance, "{}: {} instead of {}", what, actual, expected);
+    }
+
+    fn assert_angle(actual: f64, expected: f64, tolerance: f64, what: &str) {
+        let difference = (actual - expected + PI).rem_euclid(2.0 * PI) - PI;
+        assert!(difference.abs() <= tolerance, "{}: {} instead of {}", what, actual, expected);
+    }
+
+    // elements -> state vectors -> elements, which should come back as `expected`
+    fn assert_round_trip(original: OrbitalElements, expected: OrbitalElements, tolerance: f64) {
+        let (position, velocity) = original.to_state_vectors(MU);
+        let actual = OrbitalElements::from_state_vectors(position, velocity, MU);
+
+        assert_close(actual.semi_major_axis, expected.semi_major_axis, tolerance * expected.semi_major_axis.abs(), "semi-major axis");
+        assert_close(actual.eccentricity, expected.eccentricity, tolerance, "eccentricity");
+        assert_close(actual.inclination, expected.inclination, tolerance, "inclination");
+        assert_angle(actual.longitude_of_ascending_node, expected.longitude_of_ascending_node, tolerance, "ascending node");
+        assert_angle(actual.argument_of_periapsis, expected.argument_of_periapsis, tolerance, "argument of periapsis");
+        assert_angle(actual.true_anomaly, expected.true_anomaly, tolerance, "true anomaly");
+
+        // and back again to the same state vectors
+        let (round_trip_position, round_trip_velocity) = actual.to_state_vectors(MU);
+        assert!((round_trip_position - position).magnitude() <= tolerance * position.magnitude());
+        assert!((round_trip_velocity - velocity).magnitude() <= tolerance * velocity.magnitude());
+    }
+
+    #[test]
+    fn elliptic_orbits_round_trip() {
+        let orbit = elements(10.0, 0.6, 0.7, 1.2, 2.1, 0.9);
+        assert_round_trip(orbit, orbit, 1e-12);
+
+        let retrograde = elements(4.0, 0.2, 2.5, 5.0, 0.3, 4.0);
+        assert_round_trip(retrograde, retrograde, 1e-12);
+    }
+
+    #[test]
+    fn near_circular_orbits_round_trip() {
+        // periapsis is barely defined, so its angles only come back to about 1e-16 / e
+        let orbit = elements(10.0, 1e-6, 0.4, 0.8, 1.5, 3.0);
+        assert_round_trip(orbit, orbit, 1e-8);
+    }
+
+    #[test]
+    fn hyperbolic_orbits_round_trip() {
+        // within the asymptotes, cos(true anomaly) > -1 / e
+        let orbit = elements(-10.0, 1.8, 0.5, 3.5, 1.0, -1.2);
+        assert_round_trip(orbit, orbit, 1e-12);
+        assert!(!orbit.is_bound());
+    }
+
+    #[test]
+    fn circular_orbits_measure_the_anomaly_from_the_node() {
+        let orbit = elements(10.0, 0.0, 0.4, 0.8, 0.5, 2.0);
+        assert_round_trip(orbit, elements(10.0, 0.0, 0.4, 0.8, 0.0, 2.5), 1e-10);
+    }
+
+    #[test]
+    fn equatorial_orbits_measure_periapsis_from_the_x_axis() {
+        let orbit = elements(10.0, 0.3, 0.0, 1.0, 0.5, 2.0);
+        assert_round_trip(orbit, elements(10.0, 0.3, 0.0, 0.0, 1.5, 2.0), 1e-12);
+    }
+
+    #[test]
+    fn circular_equatorial_orbits_measure_the_anomaly_from_the_x_axis() {
+        let orbit = elements(10.0, 0.0, 0.0, 1.0, 0.5, 2.0);
+        assert_round_trip(orbit, elements(10.0, 0.0, 0.0, 0.0, 0.0, 3.5), 1e-10);
+    }
+}
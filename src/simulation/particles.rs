@@ -0,0 +1,189 @@
+// massless test particles: they feel the massive bodies but don't pull on anything themselves,
+// so rings and debris fields cost O(particles * bodies) rather than joining the mutual force loop
+
+use cgmath::prelude::*;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+#[cfg(feature = "render")]
+use crate::render::Particle;
+
+use super::{octree, point_mass_acceleration, Body, ForceModel, ForceSolver, OrbitalElements, Parallelism, Primary, Scalar, Simulation};
+
+#[cfg(feature = "render")]
+const PARTICLE_COLOR: [f32; 4] = [0.7, 0.7, 0.7, 1.0];
+#[cfg(feature = "render")]
+const PARTICLE_SIZE: f32 = 0.05;
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct TestParticle<S = f64> {
+    pub position: cgmath::Vector3<S>,
+    pub velocity: cgmath::Vector3<S>,
+}
+
+impl<S> TestParticle<S> {
+    pub fn new(position: cgmath::Vector3<S>, velocity: cgmath::Vector3<S>) -> Self {
+        TestParticle { position, velocity }
+    }
+}
+
+impl<S: Scalar> ForceModel<S> {
+    // accelerations at each of `positions` due to `bodies` alone
+    fn field_accelerations(&self, bodies: &[Body<S>], positions: &[cgmath::Vector3<S>]) -> Vec<cgmath::Vector3<S>> {
+        let tree = match self.solver {
+            ForceSolver::Direct => None,
+            ForceSolver::BarnesHut { theta } => Some((octree::Octree::new(bodies), theta)),
+        };
+
+        let acceleration = |position: &cgmath::Vector3<S>| match &tree {
+            None => bodies.iter().fold(cgmath::Vector3::zero(), |acceleration, b| {
+                acceleration + point_mass_acceleration(*position, b.position, b.mass, self)
+            }),
+            Some((tree, theta)) => tree.acceleration_at(*position, None, *theta, self),
+        };
+
+        match self.parallelism {
+            Parallelism::Sequential => positions.iter().map(acceleration).collect(),
+            // every particle is summed on its own, so this is deterministic either way
+            #[cfg(feature = "parallel")]
+            Parallelism::Parallel { .. } => positions.par_iter().map(acceleration).collect(),
+        }
+    }
+}
+
+/// Advances `particles` by `dt` while the massive bodies move from `before` to `after`, with a
+/// kick-drift-kick leapfrog step.
+pub(super) fn step_test_particles<S: Scalar>(
+    particles: &mut [TestParticle<S>],
+    before: &[Body<S>],
+    after: &[Body<S>],
+    dt: S,
+    forces: &ForceModel<S>,
+) {
+    let half_dt = S::from_f64(0.5) * dt;
+    let positions: Vec<_> = particles.iter().map(|p| p.position).collect();
+    let accelerations = forces.field_accelerations(before, &positions);
+
+    for (particle, acceleration) in particles.iter_mut().zip(accelerations) {
+        particle.velocity += acceleration * half_dt;
+        particle.position += particle.velocity * dt;
+    }
+
+    let positions: Vec<_> = particles.iter().map(|p| p.position).collect();
+    let accelerations = forces.field_accelerations(after, &positions);
+
+    for (particle, acceleration) in particles.iter_mut().zip(accelerations) {
+        particle.velocity += acceleration * half_dt;
+    }
+}
+
+impl<S: Scalar> Simulation<S> {
+    pub fn test_particles(&self) -> &[TestParticle<S>] {
+        &self.test_particles
+    }
+
+    #[cfg(feature = "render")]
+    pub fn particle_instances(&self) -> Vec<Particle> {
+        self.test_particles.iter().map(|particle| {
+            Particle {
+                position: particle.position.map(|x| x.into_f64() as f32),
+                color: PARTICLE_COLOR,
+                size: PARTICLE_SIZE,
+            }
+        }).collect()
+    }
+
+    /// Adds a particle that moves under the gravity of the massive bodies without affecting them.
+    ///
+    /// Adaptive timesteps only look at the massive bodies, so particles passing very close to one
+    /// may be integrated less accurately than the bodies themselves.
+    pub fn add_test_particle(&mut self, particle: TestParticle<S>) {
+        self.test_particles.push(particle);
+    }
+}
+
+impl Simulation {
+    /// Adds a test particle on the orbit around `primary` described by `elements`. Returns `false`,
+    /// adding nothing, if `primary` doesn't exist.
+    pub fn add_test_particle_on_orbit(&mut self, primary: Primary, elements: &OrbitalElements) -> bool {
+        let gravitational_constant = self.forces.gravitational_constant;
+
+        let (center, center_velocity, mass) = match primary {
+            Primary::Body(id) => match self.body(id) {
+                Some(body) => (body.position, body.velocity, body.mass),
+                None => return false,
+            },
+            Primary::Barycenter => {
+                let bodies = self.bodies();
+                let total_mass: f64 = bodies.iter().map(|b| b.mass).sum();
+
+                if total_mass <= 0.0 {
+                    return false;
+                }
+
+                let momentum: cgmath::Vector3<f64> = bodies.iter().map(|b| b.mass * b.velocity).sum();
+                (self.barycenter(), momentum / total_mass, total_mass)
+            }
+        };
+
+        let (position, velocity) = elements.to_state_vectors(gravitational_constant * mass);
+        self.add_test_particle(TestParticle::new(center + position, center_velocity + velocity));
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::{Leapfrog, Preset, Timestep, UnitSystem};
+    use std::f64::consts::PI;
+
+    #[test]
+    fn test_particles_stay_on_circular_orbits() {
+        // a unit mass at rest at the origin, with G = 1
+        let star = Body::new(cgmath::Vector3::zero(), cgmath::Vector3::zero(), 1.0);
+        let mut simulation = Simulation::from_bodies(vec![star], Box::new(Leapfrog), UnitSystem::NBody);
+
+        let radius: f64 = 2.0;
+        let start = cgmath::vec3(radius, 0.0, 0.0);
+        simulation.add_test_particle(TestParticle::new(start, cgmath::vec3(0.0, (1.0 / radius).sqrt(), 0.0)));
+
+        let period = 2.0 * PI * radius.powf(1.5);
+        let steps = 2000;
+        simulation.set_timestep(Timestep::Fixed(period / steps as f64));
+
+        for _ in 0..steps {
+            simulation.tick();
+            let distance = simulation.test_particles()[0].position.magnitude();
+            assert!((distance / radius - 1.0).abs() < 1e-5, "drifted to r = {}", distance);
+        }
+
+        let end = simulation.test_particles()[0].position;
+        assert!((end - start).magnitude() < 1e-4 * radius, "ended at {:?} rather than {:?}", end, start);
+    }
+
+    #[test]
+    fn test_particles_do_not_pull_on_bodies() {
+        let run = |solver: ForceSolver, particles: bool| {
+            let mut simulation = Simulation::from_preset(Preset::FigureEight);
+            simulation.set_force_solver(solver);
+
+            if particles {
+                for i in 0..50 {
+                    let angle = i as f64 * 0.4;
+                    let position = cgmath::vec3(angle.cos(), angle.sin(), 0.1) * (5.0 + 0.3 * i as f64);
+                    simulation.add_test_particle(TestParticle::new(position, cgmath::vec3(-angle.sin(), angle.cos(), 0.0) * 0.1));
+                }
+            }
+
+            for _ in 0..500 {
+                simulation.tick();
+            }
+            simulation.bodies().iter().map(|b| (b.position, b.velocity)).collect::<Vec<_>>()
+        };
+
+        for solver in [ForceSolver::Direct, ForceSolver::BarnesHut { theta: 0.5 }] {
+            assert_eq!(run(solver, true), run(solver, false));
+        }
+    }
+}
@@ -0,0 +1,214 @@
+// famous few-body solutions, for teaching and for checking integrators against
+//
+// Initial conditions are the published ones, in units where G = 1, scaled up like the default
+// binary: lengths by 10 and masses by 1e7 under the simulation's own G, so that velocities scale by
+// 0.1 and times by 100.
+
+use anyhow::*;
+use std::f64::consts::PI;
+
+use super::{Body, Rk4, Simulation, Timestep, UnitSystem, G};
+
+const LENGTH_SCALE: f64 = 10.0;
+const MASS_SCALE: f64 = 1e7;
+
+// RK4 steps per period for the periodic presets, enough to close each orbit to about 1e-7 of its size
+const STEPS_PER_PERIOD: f64 = 4000.0;
+// the Pythagorean problem needs adaptive steps through its close encounters; looser than this and
+// its chaotic outcome changes
+const PYTHAGOREAN_ETA: f64 = 0.002;
+
+const COLORS: [[f32; 4]; 3] = [[0.9, 0.3, 0.3, 1.0], [0.3, 0.9, 0.3, 1.0], [0.3, 0.3, 0.9, 1.0]];
+
+/// Famous solutions of the three-body problem, built by `Simulation::from_preset`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Preset {
+    /// Chenciner & Montgomery's (2000) figure-eight choreography of three equal masses, with
+    /// Simó's initial conditions.
+    FigureEight,
+    /// Lagrange's (1772) equilateral triangle of equal masses, rotating rigidly. It is unstable, so
+    /// rounding errors break it up after a few periods.
+    LagrangeTriangle,
+    /// Euler's (1767) collinear solution of equal masses on a rotating line, the middle one at rest.
+    /// Also unstable.
+    EulerCollinear,
+    /// Burrau's (1913) Pythagorean problem: masses 3, 4 and 5 released from rest at the corners of a
+    /// 3-4-5 triangle. Not periodic: after a long chaotic dance the lightest body is thrown out at
+    /// about t = 5900 (59 in the original units), leaving the other two as a binary (Szebehely &
+    /// Peters 1967).
+    Pythagorean,
+    /// Broucke's (1975) periodic orbit A1 of three equal masses, started in a line.
+    BrouckeA1,
+    /// Broucke's (1975) periodic orbit A2 of three equal masses, started in a line.
+    BrouckeA2,
+}
+
+impl Preset {
+    pub const ALL: [Preset; 6] = [
+        Preset::FigureEight,
+        Preset::LagrangeTriangle,
+        Preset::EulerCollinear,
+        Preset::Pythagorean,
+        Preset::BrouckeA1,
+        Preset::BrouckeA2,
+    ];
+
+    /// The name `from_str` accepts.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Preset::FigureEight => "figure-eight",
+            Preset::LagrangeTriangle => "lagrange-triangle",
+            Preset::EulerCollinear => "euler-collinear",
+            Preset::Pythagorean => "pythagorean",
+            Preset::BrouckeA1 => "broucke-a1",
+            Preset::BrouckeA2 => "broucke-a2",
+        }
+    }
+
+    /// Time for the bodies to return to their initial state, or `None` if they never do.
+    pub fn period(&self) -> Option<f64> {
+        let period = match self {
+            Preset::FigureEight => 6.32591398,
+            // the triangle's side is sqrt 3, so omega^2 = 3 G m / side^3 = 1 / sqrt 3
+            Preset::LagrangeTriangle => 2.0 * PI * 3f64.powf(0.25),
+            // each outer body is pulled by m / 1^2 + m / 2^2
+            Preset::EulerCollinear => 2.0 * PI / 1.25f64.sqrt(),
+            Preset::Pythagorean => return None,
+            // where these initial conditions close up again, found by integration: A1's period is
+            // 2 pi to as many digits as its initial conditions have
+            Preset::BrouckeA1 => 2.0 * PI,
+            Preset::BrouckeA2 => 7.7021643,
+        };
+
+        Some(period * time_scale())
+    }
+
+    /// The preset's bodies, with their barycenter at rest at the origin.
+    pub fn bodies(&self) -> Vec<Body> {
+        // (position, velocity, mass) in G = 1 units, all in the xy plane
+        let bodies: [([f64; 2], [f64; 2], f64); 3] = match self {
+            Preset::FigureEight => {
+                let (x, y) = (0.97000436, -0.24308753);
+                let (vx, vy) = (-0.93240737, -0.86473146);
+                [([x, y], [-vx / 2.0, -vy / 2.0], 1.0), ([-x, -y], [-vx / 2.0, -vy / 2.0], 1.0), ([0.0, 0.0], [vx, vy], 1.0)]
+            }
+            Preset::LagrangeTriangle => {
+                let speed = 3f64.powf(-0.25);
+                let vertex = |angle: f64| {
+                    let (sin, cos) = angle.sin_cos();
+                    ([cos, sin], [-speed * sin, speed * cos], 1.0)
+                };
+                [vertex(0.0), vertex(2.0 * PI / 3.0), vertex(4.0 * PI / 3.0)]
+            }
+            Preset::EulerCollinear => {
+                let speed = 1.25f64.sqrt();
+                [([-1.0, 0.0], [0.0, -speed], 1.0), ([0.0, 0.0], [0.0, 0.0], 1.0), ([1.0, 0.0], [0.0, speed], 1.0)]
+            }
+            Preset::Pythagorean => [([1.0, 3.0], [0.0, 0.0], 3.0), ([-2.0, -1.0], [0.0, 0.0], 4.0), ([1.0, -1.0], [0.0, 0.0], 5.0)],
+            Preset::BrouckeA1 => collinear([-0.9892620043, 2.2096177241, -1.2203557197], [1.9169244185, 0.1910268738, -2.1079512924]),
+            Preset::BrouckeA2 => collinear([0.3361300950, 0.7699893804, -1.1061194753], [1.5324315370, -0.6287350978, -0.9036964391]),
+        };
+
+        let velocity_scale = LENGTH_SCALE / time_scale();
+
+        bodies.iter().zip(COLORS.iter()).map(|(&([x, y], [vx, vy], mass), color)| {
+            let mut body = Body::new(
+                cgmath::vec3(x, y, 0.0) * LENGTH_SCALE,
+                cgmath::vec3(vx, vy, 0.0) * velocity_scale,
+                mass * MASS_SCALE,
+            );
+            body.color = *color;
+            body
+        }).collect()
+    }
+}
+
+// equal masses on the x axis, moving parallel to the y axis
+fn collinear(positions: [f64; 3], velocities: [f64; 3]) -> [([f64; 2], [f64; 2], f64); 3] {
+    let body = |i: usize| ([positions[i], 0.0], [0.0, velocities[i]], 1.0);
+    [body(0), body(1), body(2)]
+}
+
+// one G = 1 time unit in the scaled units
+fn time_scale() -> f64 {
+    (LENGTH_SCALE.powi(3) / (G * MASS_SCALE)).sqrt()
+}
+
+impl std::str::FromStr for Preset {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        Preset::ALL.iter().copied().find(|preset| preset.name() == name).ok_or_else(|| {
+            let names: Vec<_> = Preset::ALL.iter().map(|preset| preset.name()).collect();
+            anyhow!("unknown preset {:?}, expected one of {}", name, names.join(", "))
+        })
+    }
+}
+
+impl Simulation {
+    /// A simulation of `preset`, integrated with RK4 in steps small enough for the periodic ones to
+    /// close after each period.
+    pub fn from_preset(preset: Preset) -> Self {
+        let mut simulation = Simulation::from_bodies(preset.bodies(), Box::new(Rk4), UnitSystem::default());
+
+        simulation.set_timestep(match preset.period() {
+            Some(period) => Timestep::Fixed(period / STEPS_PER_PERIOD),
+            None => Timestep::Adaptive { eta: PYTHAGOREAN_ETA, min: 0.0, max: 0.01 * time_scale() },
+        });
+
+        simulation
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::prelude::*;
+
+    // how far the bodies are from where they started after one period, over the length scale
+    fn closure_error(preset: Preset) -> f64 {
+        let mut simulation = Simulation::from_preset(preset);
+        let initial: Vec<_> = simulation.bodies().iter().map(|b| b.position).collect();
+        let steps = STEPS_PER_PERIOD as u64;
+        for _ in 0..steps {
+            simulation.tick();
+        }
+
+        let period = preset.period().unwrap();
+        assert!((simulation.time() - period).abs() < 1e-9 * period, "{} ended at t = {} instead of {}", preset.name(), simulation.time(), period);
+
+        simulation.bodies().iter().zip(initial).map(|(b, initial)| (b.position - initial).magnitude()).fold(0.0, f64::max) / LENGTH_SCALE
+    }
+
+    // each about five times what it closes to
+    #[test]
+    fn figure_eight_closes_after_one_period() {
+        assert!(closure_error(Preset::FigureEight) < 2e-7);
+    }
+
+    // unstable, but rounding errors take more than a period to grow
+    #[test]
+    fn lagrange_triangle_closes_after_one_period() {
+        assert!(closure_error(Preset::LagrangeTriangle) < 5e-12);
+    }
+
+    #[test]
+    fn euler_collinear_closes_after_one_period() {
+        assert!(closure_error(Preset::EulerCollinear) < 5e-12);
+    }
+
+    #[test]
+    fn broucke_a1_closes_after_one_period() {
+        assert!(closure_error(Preset::BrouckeA1) < 1.5e-6);
+    }
+
+    #[test]
+    fn broucke_a2_closes_after_one_period() {
+        assert!(closure_error(Preset::BrouckeA2) < 1e-7);
+    }
+
+    #[test]
+    fn pythagorean_is_not_periodic() {
+        assert_eq!(Preset::Pythagorean.period(), None);
+    }
+}
@@ -0,0 +1,335 @@
+// trajectories written out every few ticks for offline analysis
+//
+// The binary format is little-endian throughout:
+//
+//   header: magic b"NBODYTRJ", version: u32
+//   frame:  tick: u64, time: f64, count: u32, then `count` bodies of
+//           id: u64, position: [f64; 3], velocity: [f64; 3], mass: f64
+
+use anyhow::*;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use super::{radius_for_mass, BodyId, Simulation};
+#[cfg(feature = "render")]
+use super::BODY_COLOR;
+#[cfg(feature = "render")]
+use crate::render::Instance;
+
+const MAGIC: &[u8; 8] = b"NBODYTRJ";
+const VERSION: u32 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecordingFormat {
+    /// one row per body per frame: `tick,time,id,x,y,z,vx,vy,vz,mass`
+    Csv,
+    Binary,
+}
+
+impl RecordingFormat {
+    /// CSV for `.csv` files, binary for anything else.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Self {
+        match path.as_ref().extension() {
+            Some(extension) if extension.eq_ignore_ascii_case("csv") => RecordingFormat::Csv,
+            _ => RecordingFormat::Binary,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordedBody {
+    pub id: BodyId,
+    pub position: cgmath::Vector3<f64>,
+    pub velocity: cgmath::Vector3<f64>,
+    pub mass: f64,
+}
+
+impl RecordedBody {
+    pub fn radius(&self) -> f64 {
+        radius_for_mass(self.mass)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frame {
+    pub tick: u64,
+    pub time: f64,
+    pub bodies: Vec<RecordedBody>,
+}
+
+impl Frame {
+    pub fn barycenter(&self) -> cgmath::Vector3<f64> {
+        let total_mass: f64 = self.bodies.iter().map(|b| b.mass).sum();
+        self.bodies.iter().map(|b| b.mass * b.position).sum::<cgmath::Vector3<f64>>() / total_mass
+    }
+
+    /// Recordings don't keep colors, so every body is drawn in the default one.
+    #[cfg(feature = "render")]
+    pub fn instances(&self) -> Vec<Instance> {
+        use cgmath::Rotation3;
+
+        self.bodies.iter().map(|body| {
+            Instance {
+                id: body.id,
+                position: cgmath::vec3(body.position.x as f32, body.position.y as f32, body.position.z as f32),
+                rotation: cgmath::Quaternion::from_axis_angle(cgmath::Vector3::unit_z(), cgmath::Deg(0.0)),
+                color: BODY_COLOR,
+                scale: body.radius() as f32,
+            }
+        }).collect()
+    }
+}
+
+/// Writes the state of a simulation every `every` ticks. Call `record` after each tick.
+pub struct Recorder {
+    out: Box<dyn Write>,
+    format: RecordingFormat,
+    every: u64,
+}
+
+impl Recorder {
+    pub fn new(out: Box<dyn Write>, format: RecordingFormat, every: u64) -> Result<Self> {
+        let mut recorder = Recorder { out, format, every: every.max(1) };
+
+        match format {
+            RecordingFormat::Csv => writeln!(recorder.out, "tick,time,id,x,y,z,vx,vy,vz,mass")?,
+            RecordingFormat::Binary => {
+                recorder.out.write_all(MAGIC)?;
+                recorder.out.write_all(&VERSION.to_le_bytes())?;
+            }
+        }
+
+        Ok(recorder)
+    }
+
+    /// Records to a new file at `path`, in the format its extension suggests.
+    pub fn create<P: AsRef<Path>>(path: P, every: u64) -> Result<Self> {
+        let file = std::fs::File::create(path.as_ref())
+            .with_context(|| format!("could not create recording {:?}", path.as_ref()))?;
+
+        Self::new(Box::new(BufWriter::new(file)), RecordingFormat::from_path(path), every)
+    }
+
+    /// Writes a frame if the simulation is on a multiple of `every` ticks.
+    pub fn record(&mut self, simulation: &Simulation) -> Result<()> {
+        if simulation.ticks().is_multiple_of(self.every) {
+            self.write_frame(simulation)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes a frame regardless of the tick, e.g. for the initial state.
+    pub fn write_frame(&mut self, simulation: &Simulation) -> Result<()> {
+        let (tick, time, bodies) = (simulation.ticks(), simulation.time(), simulation.bodies());
+
+        match self.format {
+            RecordingFormat::Csv => {
+                for body in bodies {
+                    writeln!(
+                        self.out,
+                        "{},{:e},{},{:e},{:e},{:e},{:e},{:e},{:e},{:e}",
+                        tick,
+                        time,
+                        body.id.0,
+                        body.position.x,
+                        body.position.y,
+                        body.position.z,
+                        body.velocity.x,
+                        body.velocity.y,
+                        body.velocity.z,
+                        body.mass,
+                    )?;
+                }
+            }
+            RecordingFormat::Binary => {
+                self.out.write_all(&tick.to_le_bytes())?;
+                self.out.write_all(&time.to_le_bytes())?;
+                self.out.write_all(&(bodies.len() as u32).to_le_bytes())?;
+
+                for body in bodies {
+                    self.out.write_all(&body.id.0.to_le_bytes())?;
+                    for value in [body.position.x, body.position.y, body.position.z] {
+                        self.out.write_all(&value.to_le_bytes())?;
+                    }
+                    for value in [body.velocity.x, body.velocity.y, body.velocity.z] {
+                        self.out.write_all(&value.to_le_bytes())?;
+                    }
+                    self.out.write_all(&body.mass.to_le_bytes())?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// Reads back the frames of a binary recording, one at a time.
+pub struct TrajectoryReader<R: Read> {
+    input: BufReader<R>,
+}
+
+impl TrajectoryReader<std::fs::File> {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = std::fs::File::open(path.as_ref())
+            .with_context(|| format!("could not open recording {:?}", path.as_ref()))?;
+
+        Self::new(file).with_context(|| format!("invalid recording {:?}", path.as_ref()))
+    }
+}
+
+impl<R: Read> TrajectoryReader<R> {
+    pub fn new(input: R) -> Result<Self> {
+        let mut reader = TrajectoryReader { input: BufReader::new(input) };
+
+        let mut magic = [0; 8];
+        reader.input.read_exact(&mut magic).context("missing header")?;
+        ensure!(&magic == MAGIC, "not an n-body trajectory recording");
+
+        let version = reader.read_u32()?;
+        ensure!(version == VERSION, "unsupported recording version {}", version);
+
+        Ok(reader)
+    }
+
+    fn read_u32(&mut self) -> Result<u32> {
+        let mut bytes = [0; 4];
+        self.input.read_exact(&mut bytes)?;
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn read_u64(&mut self) -> Result<u64> {
+        let mut bytes = [0; 8];
+        self.input.read_exact(&mut bytes)?;
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    fn read_f64(&mut self) -> Result<f64> {
+        Ok(f64::from_bits(self.read_u64()?))
+    }
+
+    fn read_vector(&mut self) -> Result<cgmath::Vector3<f64>> {
+        Ok(cgmath::vec3(self.read_f64()?, self.read_f64()?, self.read_f64()?))
+    }
+
+    /// The next frame, or `None` at the end of the recording.
+    pub fn read_frame(&mut self) -> Result<Option<Frame>> {
+        if self.input.fill_buf()?.is_empty() {
+            return Ok(None);
+        }
+
+        let tick = self.read_u64().context("truncated frame")?;
+        let time = self.read_f64().context("truncated frame")?;
+        let count = self.read_u32().context("truncated frame")?;
+
+        let bodies = (0..count).map(|_| {
+            Ok(RecordedBody {
+                id: BodyId(self.read_u64()?),
+                position: self.read_vector()?,
+                velocity: self.read_vector()?,
+                mass: self.read_f64()?,
+            })
+        }).collect::<Result<Vec<_>>>().with_context(|| format!("truncated frame at tick {}", tick))?;
+
+        Ok(Some(Frame { tick, time, bodies }))
+    }
+}
+
+impl<R: Read> Iterator for TrajectoryReader<R> {
+    type Item = Result<Frame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_frame().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // a Vec<u8> that stays readable after the recorder that owns its writer is done with it
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(bytes)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.0.borrow_mut().flush()
+        }
+    }
+
+    fn frame_of(simulation: &Simulation) -> Frame {
+        Frame {
+            tick: simulation.ticks(),
+            time: simulation.time(),
+            bodies: simulation.bodies().iter().map(|b| RecordedBody {
+                id: b.id(),
+                position: b.position,
+                velocity: b.velocity,
+                mass: b.mass,
+            }).collect(),
+        }
+    }
+
+    // a binary recording of every third tick of the default simulation, and the frames it should hold
+    fn recording() -> (Vec<u8>, Vec<Frame>) {
+        let buffer = SharedBuffer::default();
+        let mut recorder = Recorder::new(Box::new(buffer.clone()), RecordingFormat::Binary, 3).unwrap();
+        let mut simulation = Simulation::new();
+        let mut expected = vec![frame_of(&simulation)];
+        recorder.write_frame(&simulation).unwrap();
+
+        for _ in 0..10 {
+            simulation.tick();
+            recorder.record(&simulation).unwrap();
+            if simulation.ticks().is_multiple_of(3) {
+                expected.push(frame_of(&simulation));
+            }
+        }
+        recorder.flush().unwrap();
+
+        let bytes = buffer.0.borrow().clone();
+        (bytes, expected)
+    }
+
+    #[test]
+    fn binary_recordings_read_back_frame_for_frame() {
+        let (bytes, expected) = recording();
+        let frames = TrajectoryReader::new(bytes.as_slice()).unwrap().collect::<Result<Vec<_>>>().unwrap();
+
+        assert_eq!(frames.iter().map(|frame| frame.tick).collect::<Vec<_>>(), vec![0, 3, 6, 9]);
+        assert_eq!(frames, expected);
+    }
+
+    #[test]
+    fn truncated_recordings_are_rejected() {
+        let (bytes, _) = recording();
+        let truncated = &bytes[..bytes.len() - 5];
+        let frames: Vec<_> = TrajectoryReader::new(truncated).unwrap().collect();
+
+        assert_eq!(frames.len(), 4);
+        assert!(frames[..3].iter().all(|frame| frame.is_ok()));
+        assert!(frames[3].is_err());
+
+        assert!(TrajectoryReader::new(&bytes[..10]).is_err());
+    }
+
+    #[test]
+    fn recordings_with_a_bad_magic_are_rejected() {
+        let (mut bytes, _) = recording();
+        bytes[0] = b'X';
+
+        assert!(TrajectoryReader::new(bytes.as_slice()).is_err());
+        assert!(TrajectoryReader::new(&[][..]).is_err());
+    }
+}
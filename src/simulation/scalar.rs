@@ -0,0 +1,707 @@
+// the floating point types a simulation can run in, from f32 for speed to double-double for
+// studying how chaotic trajectories depend on precision
+
+use std::cmp::Ordering;
+use std::num::FpCategory;
+use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Rem, RemAssign, Sub, SubAssign};
+
+use num_traits::{Float, Num, NumCast, One, ToPrimitive, Zero};
+
+/// A floating point type positions, velocities and masses can be stored and integrated in.
+///
+/// Settings, times and everything read from or written to files stay `f64`, and are converted at
+/// the edges with `from_f64` and `into_f64`.
+pub trait Scalar: cgmath::BaseFloat + std::iter::Sum + Send + Sync + 'static {
+    /// The nearest value to `value`.
+    fn from_f64(value: f64) -> Self;
+
+    /// The nearest `f64`. Rendering narrows this further to `f32`.
+    fn into_f64(self) -> f64;
+}
+
+impl Scalar for f32 {
+    fn from_f64(value: f64) -> Self {
+        value as f32
+    }
+
+    fn into_f64(self) -> f64 {
+        self as f64
+    }
+}
+
+impl Scalar for f64 {
+    fn from_f64(value: f64) -> Self {
+        value
+    }
+
+    fn into_f64(self) -> f64 {
+        self
+    }
+}
+
+/// An unevaluated sum of two `f64`s, `hi + lo` with `|lo| <= ulp(hi) / 2`, which gives about 106
+/// bits of precision (32 significant digits) with `f64`'s exponent range.
+///
+/// Arithmetic, `sqrt`, `cbrt`, `hypot` and `powi` are accurate to double-double precision
+/// (Dekker 1971, Hida, Li & Bailey 2001). That covers everything the force evaluation and the
+/// built-in integrators need. Trigonometric, exponential and logarithmic functions, and parsing,
+/// only go through `hi`, so they are no more accurate than `f64`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DoubleDouble {
+    hi: f64,
+    lo: f64,
+}
+
+// 2^27 + 1, which splits an f64 into two halves whose products are exact
+const SPLITTER: f64 = 134217729.0;
+// 2^996, above which SPLITTER * a would overflow
+const SPLIT_THRESHOLD: f64 = 6.69692879491417e299;
+
+// a + b exactly, as the rounded sum and its error
+fn two_sum(a: f64, b: f64) -> (f64, f64) {
+    let sum = a + b;
+    let b_virtual = sum - a;
+    (sum, (a - (sum - b_virtual)) + (b - b_virtual))
+}
+
+// two_sum, for when |a| >= |b|
+fn quick_two_sum(a: f64, b: f64) -> (f64, f64) {
+    let sum = a + b;
+    (sum, b - (sum - a))
+}
+
+fn split(a: f64) -> (f64, f64) {
+    if a.abs() > SPLIT_THRESHOLD {
+        let (hi, lo) = split(a * 2f64.powi(-28));
+        return (hi * 2f64.powi(28), lo * 2f64.powi(28));
+    }
+
+    let t = SPLITTER * a;
+    let hi = t - (t - a);
+    (hi, a - hi)
+}
+
+// a * b exactly, as the rounded product and its error
+fn two_product(a: f64, b: f64) -> (f64, f64) {
+    let product = a * b;
+    let ((a_hi, a_lo), (b_hi, b_lo)) = (split(a), split(b));
+    (product, ((a_hi * b_hi - product) + a_hi * b_lo + a_lo * b_hi) + a_lo * b_lo)
+}
+
+impl DoubleDouble {
+    /// The exact sum of `hi` and `lo`, rounded to double-double.
+    pub fn new(hi: f64, lo: f64) -> Self {
+        let (hi, lo) = two_sum(hi, lo);
+        DoubleDouble::normalized(hi, lo)
+    }
+
+    /// The leading `f64`, which is also the nearest one.
+    pub fn hi(self) -> f64 {
+        self.hi
+    }
+
+    /// What `hi` leaves out.
+    pub fn lo(self) -> f64 {
+        self.lo
+    }
+
+    // once hi overflows or turns NaN, lo is meaningless and would only spread NaNs
+    fn normalized(hi: f64, lo: f64) -> Self {
+        if hi.is_finite() {
+            DoubleDouble { hi, lo }
+        } else {
+            DoubleDouble { hi, lo: 0.0 }
+        }
+    }
+
+    // from a pair with |hi| >= |lo| that may overlap
+    fn renormalized((hi, lo): (f64, f64)) -> Self {
+        let (hi, lo) = quick_two_sum(hi, lo);
+        DoubleDouble::normalized(hi, lo)
+    }
+
+    fn mul_f64(self, b: f64) -> Self {
+        let (p, e) = two_product(self.hi, b);
+        DoubleDouble::renormalized((p, e + self.lo * b))
+    }
+
+    fn square(self) -> Self {
+        let (p, e) = two_product(self.hi, self.hi);
+        DoubleDouble::renormalized((p, e + 2.0 * self.hi * self.lo + self.lo * self.lo))
+    }
+
+    // through hi alone, for the functions that aren't worth computing in full precision
+    fn via_f64(self, function: impl Fn(f64) -> f64) -> Self {
+        DoubleDouble::from_f64(function(self.hi))
+    }
+}
+
+impl From<f64> for DoubleDouble {
+    fn from(value: f64) -> Self {
+        DoubleDouble::from_f64(value)
+    }
+}
+
+impl Scalar for DoubleDouble {
+    fn from_f64(value: f64) -> Self {
+        DoubleDouble { hi: value, lo: 0.0 }
+    }
+
+    fn into_f64(self) -> f64 {
+        self.hi
+    }
+}
+
+impl PartialOrd for DoubleDouble {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match self.hi.partial_cmp(&other.hi)? {
+            Ordering::Equal => self.lo.partial_cmp(&other.lo),
+            ordering => Some(ordering),
+        }
+    }
+}
+
+impl Neg for DoubleDouble {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        DoubleDouble { hi: -self.hi, lo: -self.lo }
+    }
+}
+
+impl Add for DoubleDouble {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        let (s, e) = two_sum(self.hi, other.hi);
+        let (t, f) = two_sum(self.lo, other.lo);
+        let (s, e) = quick_two_sum(s, e + t);
+        DoubleDouble::renormalized((s, e + f))
+    }
+}
+
+impl Sub for DoubleDouble {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        self + -other
+    }
+}
+
+impl Mul for DoubleDouble {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        let (p, e) = two_product(self.hi, other.hi);
+        DoubleDouble::renormalized((p, e + (self.hi * other.lo + self.lo * other.hi)))
+    }
+}
+
+impl Div for DoubleDouble {
+    type Output = Self;
+
+    // long division, one f64 digit at a time
+    fn div(self, other: Self) -> Self {
+        let q1 = self.hi / other.hi;
+        if !q1.is_finite() {
+            return DoubleDouble::from_f64(q1);
+        }
+
+        let remainder = self - other.mul_f64(q1);
+        let q2 = remainder.hi / other.hi;
+        let remainder = remainder - other.mul_f64(q2);
+        let q3 = remainder.hi / other.hi;
+
+        DoubleDouble::renormalized(quick_two_sum(q1, q2)) + DoubleDouble::from_f64(q3)
+    }
+}
+
+impl Rem for DoubleDouble {
+    type Output = Self;
+
+    fn rem(self, other: Self) -> Self {
+        self - other * (self / other).trunc()
+    }
+}
+
+macro_rules! assign_ops {
+    ($($trait:ident $method:ident $op:tt),*) => {
+        $(impl $trait for DoubleDouble {
+            fn $method(&mut self, other: Self) {
+                *self = *self $op other;
+            }
+        })*
+    };
+}
+
+assign_ops!(AddAssign add_assign +, SubAssign sub_assign -, MulAssign mul_assign *, DivAssign div_assign /, RemAssign rem_assign %);
+
+impl std::iter::Sum for DoubleDouble {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(DoubleDouble::zero(), Add::add)
+    }
+}
+
+impl Zero for DoubleDouble {
+    fn zero() -> Self {
+        DoubleDouble::from_f64(0.0)
+    }
+
+    fn is_zero(&self) -> bool {
+        self.hi == 0.0
+    }
+}
+
+impl One for DoubleDouble {
+    fn one() -> Self {
+        DoubleDouble::from_f64(1.0)
+    }
+}
+
+impl Num for DoubleDouble {
+    type FromStrRadixErr = <f64 as Num>::FromStrRadixErr;
+
+    fn from_str_radix(source: &str, radix: u32) -> Result<Self, Self::FromStrRadixErr> {
+        <f64 as Num>::from_str_radix(source, radix).map(DoubleDouble::from_f64)
+    }
+}
+
+impl ToPrimitive for DoubleDouble {
+    fn to_i64(&self) -> Option<i64> {
+        let truncated = self.trunc();
+        truncated.hi.to_i64()?.checked_add(truncated.lo.to_i64()?)
+    }
+
+    fn to_u64(&self) -> Option<u64> {
+        let truncated = self.trunc();
+        truncated.hi.to_u64()?.checked_add_signed(truncated.lo.to_i64()?)
+    }
+
+    fn to_f64(&self) -> Option<f64> {
+        Some(self.hi)
+    }
+}
+
+impl NumCast for DoubleDouble {
+    fn from<T: ToPrimitive>(n: T) -> Option<Self> {
+        n.to_f64().map(DoubleDouble::from_f64)
+    }
+}
+
+impl Float for DoubleDouble {
+    fn nan() -> Self {
+        DoubleDouble::from_f64(f64::NAN)
+    }
+
+    fn infinity() -> Self {
+        DoubleDouble::from_f64(f64::INFINITY)
+    }
+
+    fn neg_infinity() -> Self {
+        DoubleDouble::from_f64(f64::NEG_INFINITY)
+    }
+
+    fn neg_zero() -> Self {
+        DoubleDouble::from_f64(-0.0)
+    }
+
+    fn min_value() -> Self {
+        DoubleDouble::from_f64(f64::MIN)
+    }
+
+    fn min_positive_value() -> Self {
+        DoubleDouble::from_f64(f64::MIN_POSITIVE)
+    }
+
+    fn epsilon() -> Self {
+        DoubleDouble::from_f64(2f64.powi(-104))
+    }
+
+    fn max_value() -> Self {
+        DoubleDouble::from_f64(f64::MAX)
+    }
+
+    fn is_nan(self) -> bool {
+        self.hi.is_nan()
+    }
+
+    fn is_infinite(self) -> bool {
+        self.hi.is_infinite()
+    }
+
+    fn is_finite(self) -> bool {
+        self.hi.is_finite()
+    }
+
+    fn is_normal(self) -> bool {
+        self.hi.is_normal()
+    }
+
+    fn classify(self) -> FpCategory {
+        self.hi.classify()
+    }
+
+    fn floor(self) -> Self {
+        let hi = self.hi.floor();
+
+        if hi == self.hi {
+            DoubleDouble::renormalized((hi, self.lo.floor()))
+        } else {
+            DoubleDouble::from_f64(hi)
+        }
+    }
+
+    fn ceil(self) -> Self {
+        -(-self).floor()
+    }
+
+    fn round(self) -> Self {
+        let half = DoubleDouble::from_f64(0.5);
+
+        if self.is_sign_negative() {
+            -(-self + half).floor()
+        } else {
+            (self + half).floor()
+        }
+    }
+
+    fn trunc(self) -> Self {
+        if self.is_sign_negative() {
+            self.ceil()
+        } else {
+            self.floor()
+        }
+    }
+
+    fn fract(self) -> Self {
+        self - self.trunc()
+    }
+
+    fn abs(self) -> Self {
+        if self.is_sign_negative() {
+            -self
+        } else {
+            self
+        }
+    }
+
+    fn signum(self) -> Self {
+        DoubleDouble::from_f64(self.hi.signum())
+    }
+
+    fn is_sign_positive(self) -> bool {
+        !self.is_sign_negative()
+    }
+
+    fn is_sign_negative(self) -> bool {
+        self.hi.is_sign_negative() || (self.hi == 0.0 && self.lo.is_sign_negative())
+    }
+
+    fn mul_add(self, a: Self, b: Self) -> Self {
+        self * a + b
+    }
+
+    fn recip(self) -> Self {
+        DoubleDouble::one() / self
+    }
+
+    fn powi(self, n: i32) -> Self {
+        let mut result = DoubleDouble::one();
+        let mut base = self;
+        let mut exponent = n.unsigned_abs();
+
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result *= base;
+            }
+            base = base.square();
+            exponent >>= 1;
+        }
+
+        if n < 0 {
+            result.recip()
+        } else {
+            result
+        }
+    }
+
+    fn powf(self, n: Self) -> Self {
+        if n == n.trunc() && n.abs() <= DoubleDouble::from_f64(i32::MAX as f64) {
+            self.powi(n.hi as i32)
+        } else {
+            self.via_f64(|x| x.powf(n.hi))
+        }
+    }
+
+    // one Newton step from the f64 root doubles its precision
+    fn sqrt(self) -> Self {
+        if self.hi <= 0.0 || !self.hi.is_finite() {
+            return DoubleDouble::from_f64(self.hi.sqrt());
+        }
+
+        let root = self.hi.sqrt();
+        let correction = (self - DoubleDouble::from_f64(root).square()).hi / (2.0 * root);
+        DoubleDouble::new(root, correction)
+    }
+
+    fn exp(self) -> Self {
+        self.via_f64(f64::exp)
+    }
+
+    fn exp2(self) -> Self {
+        self.via_f64(f64::exp2)
+    }
+
+    fn ln(self) -> Self {
+        self.via_f64(f64::ln)
+    }
+
+    fn log(self, base: Self) -> Self {
+        self.via_f64(|x| x.log(base.hi))
+    }
+
+    fn log2(self) -> Self {
+        self.via_f64(f64::log2)
+    }
+
+    fn log10(self) -> Self {
+        self.via_f64(f64::log10)
+    }
+
+    fn max(self, other: Self) -> Self {
+        if self.is_nan() || other > self {
+            other
+        } else {
+            self
+        }
+    }
+
+    fn min(self, other: Self) -> Self {
+        if self.is_nan() || other < self {
+            other
+        } else {
+            self
+        }
+    }
+
+    fn abs_sub(self, other: Self) -> Self {
+        if self <= other {
+            DoubleDouble::zero()
+        } else {
+            self - other
+        }
+    }
+
+    fn cbrt(self) -> Self {
+        let root = DoubleDouble::from_f64(self.hi.cbrt());
+
+        if self.hi == 0.0 || !self.hi.is_finite() {
+            return root;
+        }
+
+        root + (self - root.powi(3)) / (DoubleDouble::from_f64(3.0) * root.square())
+    }
+
+    fn hypot(self, other: Self) -> Self {
+        (self.square() + other.square()).sqrt()
+    }
+
+    fn sin(self) -> Self {
+        self.via_f64(f64::sin)
+    }
+
+    fn cos(self) -> Self {
+        self.via_f64(f64::cos)
+    }
+
+    fn tan(self) -> Self {
+        self.via_f64(f64::tan)
+    }
+
+    fn asin(self) -> Self {
+        self.via_f64(f64::asin)
+    }
+
+    fn acos(self) -> Self {
+        self.via_f64(f64::acos)
+    }
+
+    fn atan(self) -> Self {
+        self.via_f64(f64::atan)
+    }
+
+    fn atan2(self, other: Self) -> Self {
+        self.via_f64(|y| y.atan2(other.hi))
+    }
+
+    fn sin_cos(self) -> (Self, Self) {
+        (self.sin(), self.cos())
+    }
+
+    fn exp_m1(self) -> Self {
+        self.via_f64(f64::exp_m1)
+    }
+
+    fn ln_1p(self) -> Self {
+        self.via_f64(f64::ln_1p)
+    }
+
+    fn sinh(self) -> Self {
+        self.via_f64(f64::sinh)
+    }
+
+    fn cosh(self) -> Self {
+        self.via_f64(f64::cosh)
+    }
+
+    fn tanh(self) -> Self {
+        self.via_f64(f64::tanh)
+    }
+
+    fn asinh(self) -> Self {
+        self.via_f64(f64::asinh)
+    }
+
+    fn acosh(self) -> Self {
+        self.via_f64(f64::acosh)
+    }
+
+    fn atanh(self) -> Self {
+        self.via_f64(f64::atanh)
+    }
+
+    fn integer_decode(self) -> (u64, i16, i8) {
+        self.hi.integer_decode()
+    }
+}
+
+impl approx::AbsDiffEq for DoubleDouble {
+    type Epsilon = Self;
+
+    fn default_epsilon() -> Self {
+        DoubleDouble::epsilon()
+    }
+
+    fn abs_diff_eq(&self, other: &Self, epsilon: Self) -> bool {
+        (*self - *other).abs() <= epsilon
+    }
+}
+
+impl approx::RelativeEq for DoubleDouble {
+    fn default_max_relative() -> Self {
+        DoubleDouble::epsilon()
+    }
+
+    fn relative_eq(&self, other: &Self, epsilon: Self, max_relative: Self) -> bool {
+        if self == other {
+            return true;
+        }
+        if self.is_infinite() || other.is_infinite() {
+            return false;
+        }
+
+        let difference = (*self - *other).abs();
+        difference <= epsilon || difference <= self.abs().max(other.abs()) * max_relative
+    }
+}
+
+impl approx::UlpsEq for DoubleDouble {
+    fn default_max_ulps() -> u32 {
+        4
+    }
+
+    // a double-double has no fixed ulp, so this counts units of epsilon relative to the larger value
+    fn ulps_eq(&self, other: &Self, epsilon: Self, max_ulps: u32) -> bool {
+        let max_relative = DoubleDouble::epsilon() * DoubleDouble::from_f64(max_ulps as f64);
+        approx::RelativeEq::relative_eq(self, other, epsilon, max_relative)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dd(value: f64) -> DoubleDouble {
+        DoubleDouble::from_f64(value)
+    }
+
+    #[test]
+    fn products_and_sums_are_exact() {
+        // (1 + 2^-30)^2 = 1 + 2^-29 + 2^-60, whose last term falls off the end of an f64
+        let a = 1.0 + 2f64.powi(-30);
+        assert_eq!(two_product(a, a), (1.0 + 2f64.powi(-29), 2f64.powi(-60)));
+        assert_eq!(two_sum(1.0, 2f64.powi(-60)), (1.0, 2f64.powi(-60)));
+        assert_eq!(two_sum(2f64.powi(-60), 1.0), (1.0, 2f64.powi(-60)));
+    }
+
+    #[test]
+    fn a_third_times_three_is_one() {
+        let third = dd(1.0) / dd(3.0);
+        assert_ne!(third.lo(), 0.0);
+        assert_eq!(third * dd(3.0) - dd(1.0), dd(0.0));
+    }
+
+    #[test]
+    fn division_undoes_multiplication() {
+        for &(x, y) in &[(1.0, 10.0), (2.0, 7.0), (-5.0, 1e-3), (1e300, 3e-5)] {
+            let (x, y) = (dd(x), dd(y));
+            let error = ((x / y) * y - x).abs() / x.abs();
+            assert!(error <= DoubleDouble::epsilon(), "{:?} / {:?}: {:e}", x, y, error.hi());
+        }
+    }
+
+    #[test]
+    fn roots_are_exact_to_double_double_precision() {
+        let two = dd(2.0);
+        let root = two.sqrt();
+        assert_ne!(root.lo(), 0.0);
+        assert!((root * root - two).abs() <= DoubleDouble::epsilon() * two);
+
+        let root = two.cbrt();
+        assert!((root.powi(3) - two).abs() <= DoubleDouble::epsilon() * two);
+        assert_eq!(dd(4.0).sqrt(), dd(2.0));
+        assert_eq!(dd(-8.0).cbrt(), dd(-2.0));
+        assert_eq!(dd(3.0).hypot(dd(4.0)), dd(5.0));
+    }
+
+    #[test]
+    fn rounding_sees_below_hi() {
+        // just below and just above -2, and just above -2.5, none of which an f64 can tell from them
+        let (below, above) = (DoubleDouble::new(-2.0, -1e-20), DoubleDouble::new(-2.0, 1e-20));
+        assert_eq!(below.floor(), dd(-3.0));
+        assert_eq!(above.floor(), dd(-2.0));
+        assert_eq!(below.ceil(), dd(-2.0));
+        assert_eq!(above.ceil(), dd(-1.0));
+        assert_eq!(below.trunc(), dd(-2.0));
+        assert_eq!(above.trunc(), dd(-1.0));
+        assert_eq!(below.round(), dd(-2.0));
+        assert_eq!(DoubleDouble::new(-2.5, 1e-20).round(), dd(-2.0));
+        assert_eq!(DoubleDouble::new(-2.5, -1e-20).round(), dd(-3.0));
+        assert_eq!(dd(-2.5).round(), dd(-3.0));
+        assert_eq!(above.fract(), DoubleDouble::new(-1.0, 1e-20));
+    }
+
+    #[test]
+    fn integer_conversions_include_lo() {
+        let big = 2f64.powi(60);
+        assert_eq!(DoubleDouble::new(big, 1.0).to_i64(), Some((1 << 60) + 1));
+        assert_eq!(DoubleDouble::new(big, -0.5).to_i64(), Some((1 << 60) - 1));
+        assert_eq!(DoubleDouble::new(-big, -1.0).to_i64(), Some(-(1 << 60) - 1));
+        assert_eq!(DoubleDouble::new(big, -1.0).to_u64(), Some((1 << 60) - 1));
+        assert_eq!(dd(-1.0).to_u64(), None);
+        assert_eq!(dd(1e20).to_i64(), None);
+    }
+
+    #[test]
+    fn approximate_comparisons_resolve_lo() {
+        let one = dd(1.0);
+        let nearly_one = DoubleDouble::new(1.0, 1e-20);
+        assert_eq!(nearly_one.hi(), one.hi());
+
+        assert!(approx::abs_diff_ne!(one, nearly_one));
+        assert!(approx::relative_ne!(one, nearly_one));
+        assert!(approx::relative_eq!(one, nearly_one, max_relative = dd(1e-19)));
+        assert!(approx::ulps_eq!(one, DoubleDouble::new(1.0, 2f64.powi(-104))));
+        assert!(approx::ulps_ne!(one, nearly_one));
+    }
+}
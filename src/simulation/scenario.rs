@@ -0,0 +1,256 @@
+// human-editable initial conditions, loaded from TOML
+
+use anyhow::*;
+use serde::Deserialize;
+use std::path::Path;
+
+use super::{
+    disk_galaxy, place_bodies, Body, CollisionMode, ForceSolver, GalaxyModel, IntegratorKind, Simulation, Softening,
+    Summation, TestParticle, Timestep, UnitSystem, BODY_COLOR,
+};
+
+/// Initial bodies plus the global settings to run them with.
+///
+/// ```toml
+/// [settings]
+/// gravitational_constant = 1e-8
+/// integrator = "rk4"
+/// dt = 0.5
+/// softening = { plummer = 0.05 }
+///
+/// [[body]]
+/// name = "primary"
+/// position = [0.0, 0.0, 0.0]
+/// velocity = [0.0, 0.0, 0.0]
+/// mass = 1e7
+/// color = [1.0, 0.8, 0.2, 1.0]
+///
+/// [[particle]]
+/// position = [5.0, 0.0, 0.0]
+/// velocity = [0.0, 0.14, 0.0]
+///
+/// [[galaxy]]
+/// seed = 1
+/// position = [-100.0, 0.0, 0.0]
+/// velocity = [0.02, 0.0, 0.0]
+/// axis = [0.0, 0.5, 1.0]
+/// disk_mass = 1e9
+/// scale_length = 10.0
+/// halo_bodies = 2000
+/// halo_mass_ratio = 4.0
+/// ```
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    #[serde(default)]
+    pub settings: Settings,
+    #[serde(rename = "body", default)]
+    pub bodies: Vec<BodyDescription>,
+    /// massless test particles
+    #[serde(rename = "particle", default)]
+    pub particles: Vec<ParticleDescription>,
+    /// generated disk galaxies, whose bodies follow the ones listed individually
+    #[serde(rename = "galaxy", default)]
+    pub galaxies: Vec<GalaxyDescription>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    /// what every quantity in the scenario is measured in, dimensionless with G = `G` by default
+    pub units: Option<UnitSystem>,
+    /// shorthand for dimensionless units with this G
+    pub gravitational_constant: Option<f64>,
+    pub integrator: IntegratorKind,
+    pub dt: f64,
+    /// if present, steps adapt to close encounters and `dt` is ignored
+    pub adaptive: Option<AdaptiveSettings>,
+    pub softening: Softening,
+    pub force_solver: ForceSolver,
+    pub collisions: CollisionMode,
+    pub summation: Summation,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            units: None,
+            gravitational_constant: None,
+            integrator: IntegratorKind::Leapfrog,
+            dt: 1.0,
+            adaptive: None,
+            softening: Softening::None,
+            force_solver: ForceSolver::Direct,
+            collisions: CollisionMode::None,
+            summation: Summation::Naive,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AdaptiveSettings {
+    pub eta: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BodyDescription {
+    pub name: Option<String>,
+    pub position: [f64; 3],
+    #[serde(default)]
+    pub velocity: [f64; 3],
+    pub mass: f64,
+    pub color: Option<[f32; 4]>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ParticleDescription {
+    pub position: [f64; 3],
+    #[serde(default)]
+    pub velocity: [f64; 3],
+}
+
+/// A disk galaxy from `disk_galaxy`, moved into place. Anything left out takes its value from
+/// `GalaxyModel::default()`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GalaxyDescription {
+    pub seed: u64,
+    pub position: [f64; 3],
+    pub velocity: [f64; 3],
+    /// the direction the disk's spin points in
+    pub axis: [f64; 3],
+    pub disk_mass: f64,
+    pub scale_length: f64,
+    pub disk_bodies: usize,
+    pub bulge_bodies: usize,
+    pub bulge_mass_ratio: f64,
+    pub halo_bodies: usize,
+    pub halo_mass_ratio: f64,
+}
+
+impl Default for GalaxyDescription {
+    fn default() -> Self {
+        let model = GalaxyModel::default();
+
+        GalaxyDescription {
+            seed: 0,
+            position: [0.0; 3],
+            velocity: [0.0; 3],
+            axis: [0.0, 0.0, 1.0],
+            disk_mass: model.disk_mass,
+            scale_length: model.scale_length,
+            disk_bodies: model.disk_bodies,
+            bulge_bodies: model.bulge_bodies,
+            bulge_mass_ratio: model.bulge_mass_ratio,
+            halo_bodies: model.halo_bodies,
+            halo_mass_ratio: model.halo_mass_ratio,
+        }
+    }
+}
+
+impl GalaxyDescription {
+    pub fn model(&self) -> GalaxyModel {
+        GalaxyModel {
+            disk_mass: self.disk_mass,
+            scale_length: self.scale_length,
+            disk_bodies: self.disk_bodies,
+            bulge_bodies: self.bulge_bodies,
+            bulge_mass_ratio: self.bulge_mass_ratio,
+            halo_bodies: self.halo_bodies,
+            halo_mass_ratio: self.halo_mass_ratio,
+        }
+    }
+}
+
+impl Settings {
+    pub fn units(&self) -> UnitSystem {
+        match (self.units, self.gravitational_constant) {
+            (Some(units), _) => units,
+            (None, Some(gravitational_constant)) => UnitSystem::Dimensionless { gravitational_constant },
+            (None, None) => UnitSystem::default(),
+        }
+    }
+}
+
+impl std::str::FromStr for Scenario {
+    type Err = Error;
+
+    fn from_str(source: &str) -> Result<Self> {
+        let scenario: Scenario = toml::from_str(source)?;
+
+        if scenario.settings.units.is_some() && scenario.settings.gravitational_constant.is_some() {
+            bail!("settings can give units or a gravitational constant, not both");
+        }
+
+        let gravitational_constant = scenario.settings.units().gravitational_constant();
+        if gravitational_constant.is_nan() || gravitational_constant <= 0.0 {
+            bail!("the gravitational constant must be positive, not {}", gravitational_constant);
+        }
+
+        for (i, body) in scenario.bodies.iter().enumerate() {
+            if body.mass.is_nan() || body.mass <= 0.0 {
+                bail!("body {} ({}) must have a positive mass", i, body.name.as_deref().unwrap_or("unnamed"));
+            }
+        }
+
+        for (i, galaxy) in scenario.galaxies.iter().enumerate() {
+            if galaxy.axis == [0.0; 3] {
+                bail!("galaxy {} needs a nonzero axis", i);
+            }
+        }
+
+        Ok(scenario)
+    }
+}
+
+impl Scenario {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let source = std::fs::read_to_string(path.as_ref())
+            .with_context(|| format!("could not read scenario {:?}", path.as_ref()))?;
+
+        source.parse().with_context(|| format!("invalid scenario {:?}", path.as_ref()))
+    }
+}
+
+impl Simulation {
+    /// Fails if a galaxy can't be generated from its description.
+    pub fn from_scenario(scenario: &Scenario) -> Result<Self> {
+        let settings = &scenario.settings;
+        let units = settings.units();
+
+        let mut bodies: Vec<Body> = scenario.bodies.iter().map(|description| {
+            let mut body = Body::new(description.position.into(), description.velocity.into(), description.mass);
+            body.name = description.name.clone();
+            body.color = description.color.unwrap_or(BODY_COLOR);
+            body
+        }).collect();
+
+        for (i, description) in scenario.galaxies.iter().enumerate() {
+            let mut galaxy = disk_galaxy(&description.model(), units.gravitational_constant(), description.seed)
+                .with_context(|| format!("invalid galaxy {}", i))?;
+            place_bodies(&mut galaxy, description.axis.into(), description.position.into(), description.velocity.into());
+            bodies.extend(galaxy);
+        }
+
+        let mut simulation = Simulation::from_bodies(bodies, settings.integrator.integrator(), units);
+        simulation.set_force_solver(settings.force_solver);
+        simulation.set_softening(settings.softening);
+        simulation.set_collision_mode(settings.collisions)?;
+        simulation.set_summation(settings.summation);
+        simulation.set_timestep(match settings.adaptive {
+            Some(AdaptiveSettings { eta, min, max }) => Timestep::Adaptive { eta, min, max },
+            None => Timestep::Fixed(settings.dt),
+        });
+
+        for description in &scenario.particles {
+            simulation.add_test_particle(TestParticle::new(description.position.into(), description.velocity.into()));
+        }
+
+        Ok(simulation)
+    }
+}
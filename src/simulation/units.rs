@@ -0,0 +1,184 @@
+// physical unit systems, so that real data can be simulated and results read back in real units
+
+use anyhow::*;
+use serde::{Deserialize, Serialize};
+
+use super::{radius_for_mass, Body, G};
+
+/// The gravitational constant in SI units (CODATA 2018).
+pub const G_SI: f64 = 6.6743e-11;
+
+// the heliocentric gravitational constant in m^3/s^2, known far better than G or the Sun's mass,
+// which is why the solar mass unit is defined through it
+pub(super) const GM_SUN: f64 = 1.32712440041939e20;
+// the IAU nominal solar radius, in m
+const SOLAR_RADIUS: f64 = 6.957e8;
+const ASTRONOMICAL_UNIT: f64 = 1.495978707e11;
+const KILOPARSEC: f64 = 1e3 * 648000.0 / std::f64::consts::PI * ASTRONOMICAL_UNIT;
+const DAY: f64 = 86400.0;
+const MEGAYEAR: f64 = 1e6 * 365.25 * DAY;
+
+/// The units a simulation's positions, velocities, masses and times are in, which fix its G.
+///
+/// `Si`, `Astronomical` and `Galactic` are physical and convert into each other with `conversion`.
+/// `NBody` and `Dimensionless` are scale-free, so they only convert to themselves.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum UnitSystem {
+    /// metres, seconds and kilograms
+    Si,
+    /// astronomical units, days and solar masses, for planetary systems
+    Astronomical,
+    /// kiloparsecs, megayears (of Julian years) and solar masses, for clusters and galaxies
+    Galactic,
+    /// Hénon's N-body units, in which G = 1
+    NBody,
+    /// arbitrary units in which G takes the given value, like the default `G`
+    Dimensionless { gravitational_constant: f64 },
+}
+
+impl Default for UnitSystem {
+    fn default() -> Self {
+        UnitSystem::Dimensionless { gravitational_constant: G }
+    }
+}
+
+impl UnitSystem {
+    /// Metres in one unit of length, or `None` if the units are scale-free.
+    pub fn length(&self) -> Option<f64> {
+        match self {
+            UnitSystem::Si => Some(1.0),
+            UnitSystem::Astronomical => Some(ASTRONOMICAL_UNIT),
+            UnitSystem::Galactic => Some(KILOPARSEC),
+            UnitSystem::NBody | UnitSystem::Dimensionless { .. } => None,
+        }
+    }
+
+    /// Seconds in one unit of time, or `None` if the units are scale-free.
+    pub fn time(&self) -> Option<f64> {
+        match self {
+            UnitSystem::Si => Some(1.0),
+            UnitSystem::Astronomical => Some(DAY),
+            UnitSystem::Galactic => Some(MEGAYEAR),
+            UnitSystem::NBody | UnitSystem::Dimensionless { .. } => None,
+        }
+    }
+
+    /// Kilograms in one unit of mass, or `None` if the units are scale-free.
+    pub fn mass(&self) -> Option<f64> {
+        match self {
+            UnitSystem::Si => Some(1.0),
+            UnitSystem::Astronomical | UnitSystem::Galactic => Some(GM_SUN / G_SI),
+            UnitSystem::NBody | UnitSystem::Dimensionless { .. } => None,
+        }
+    }
+
+    /// G expressed in these units.
+    pub fn gravitational_constant(&self) -> f64 {
+        match self {
+            UnitSystem::Si => G_SI,
+            // exact by construction, since the mass unit is GM_SUN / G, rather than G_SI converted
+            UnitSystem::Astronomical => GM_SUN * DAY * DAY / ASTRONOMICAL_UNIT.powi(3),
+            UnitSystem::Galactic => GM_SUN * MEGAYEAR * MEGAYEAR / KILOPARSEC.powi(3),
+            UnitSystem::NBody => 1.0,
+            UnitSystem::Dimensionless { gravitational_constant } => *gravitational_constant,
+        }
+    }
+
+    /// Radius of a body of `mass` for collisions. In dimensionless units this is its drawn size; in
+    /// physical ones, a sphere as dense as the Sun on average, which gets the Sun itself right and
+    /// makes rocky planets a little large. `None` in N-body units, which have no scale to size
+    /// bodies by.
+    pub fn collision_radius(&self, mass: f64) -> Option<f64> {
+        match self {
+            UnitSystem::Dimensionless { .. } => Some(radius_for_mass(mass)),
+            UnitSystem::NBody => None,
+            _ => {
+                let solar_masses = mass * self.mass()? / (GM_SUN / G_SI);
+                Some(SOLAR_RADIUS * solar_masses.cbrt() / self.length()?)
+            }
+        }
+    }
+
+    /// Factors that take quantities in these units to `target`'s.
+    pub fn conversion(&self, target: UnitSystem) -> Result<Conversion> {
+        if *self == target {
+            return Ok(Conversion::IDENTITY);
+        }
+
+        let scales = |units: &UnitSystem| Some((units.length()?, units.time()?, units.mass()?));
+
+        match (scales(self), scales(&target)) {
+            (Some((length, time, mass)), Some((target_length, target_time, target_mass))) => Ok(Conversion {
+                length: length / target_length,
+                time: time / target_time,
+                mass: mass / target_mass,
+            }),
+            _ => bail!("cannot convert between {:?} and {:?}, only between physical units", self, target),
+        }
+    }
+}
+
+/// What one unit of length, time and mass in one `UnitSystem` is in another, from
+/// `UnitSystem::conversion`. Multiply a quantity by the factor for its dimension to convert it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Conversion {
+    pub length: f64,
+    pub time: f64,
+    pub mass: f64,
+}
+
+impl Conversion {
+    const IDENTITY: Conversion = Conversion { length: 1.0, time: 1.0, mass: 1.0 };
+
+    pub fn velocity(&self) -> f64 {
+        self.length / self.time
+    }
+
+    pub fn acceleration(&self) -> f64 {
+        self.velocity() / self.time
+    }
+
+    pub fn energy(&self) -> f64 {
+        self.mass * self.velocity().powi(2)
+    }
+
+    pub fn momentum(&self) -> f64 {
+        self.mass * self.velocity()
+    }
+
+    pub fn angular_momentum(&self) -> f64 {
+        self.momentum() * self.length
+    }
+
+    /// `body` with its position, velocity and mass converted.
+    pub fn body(&self, body: &Body) -> Body {
+        Body {
+            position: body.position * self.length,
+            velocity: body.velocity * self.velocity(),
+            mass: body.mass * self.mass,
+            compensation: None,
+            ..body.clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_sun_collides_at_its_own_radius_in_every_physical_unit() {
+        let solar_mass = GM_SUN / G_SI;
+        for units in [UnitSystem::Si, UnitSystem::Astronomical, UnitSystem::Galactic] {
+            let radius = units.collision_radius(solar_mass / units.mass().unwrap()).unwrap();
+            approx::assert_relative_eq!(radius * units.length().unwrap(), SOLAR_RADIUS, max_relative = 1e-12);
+        }
+    }
+
+    #[test]
+    fn only_nbody_units_leave_bodies_without_a_size() {
+        assert_eq!(UnitSystem::NBody.collision_radius(1.0), None);
+        assert_eq!(UnitSystem::default().collision_radius(1e7), Some(1.0));
+    }
+}
@@ -3,8 +3,10 @@ use image::GenericImageView;
 use std::path::Path;
 
 pub struct Texture {
+    #[allow(dead_code)]
     pub texture: wgpu::Texture,
     pub view: wgpu::TextureView,
+    #[allow(dead_code)]
     pub sampler: wgpu::Sampler,
 }
 
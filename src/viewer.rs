@@ -0,0 +1,235 @@
+// the interactive window: draws the simulation and lets bodies be clicked in
+
+use futures::executor::block_on;
+use winit::{
+    dpi::{PhysicalPosition, PhysicalSize},
+    event::*,
+    event_loop::{ControlFlow, EventLoop},
+    window::WindowBuilder,
+};
+
+use nbody_3d_v2::{render, simulation};
+
+use crate::replay::Replay;
+
+// ticks between diagnostics log lines
+const DIAGNOSTICS_INTERVAL: u64 = 600;
+
+// where F5 saves the running simulation
+const CHECKPOINT_PATH: &str = "checkpoint.toml";
+
+fn window_to_view_space(window_size: PhysicalSize<u32>, window_position: PhysicalPosition<f64>) -> cgmath::Vector2<f64> {
+    cgmath::Vector2 {
+        x: window_position.x / window_size.width as f64,
+        y: window_position.y / window_size.height as f64,
+    }
+}
+
+/// What the window shows: a simulation stepped live, or a recorded trajectory played back.
+pub enum Source {
+    Live(Box<simulation::Simulation>),
+    Replay(Replay),
+}
+
+pub fn run(mut source: Source) -> ! {
+    let event_loop = EventLoop::new();
+    let window = WindowBuilder::new().build(&event_loop).unwrap();
+    let mut running = true;
+    let mut last_cursor_position: Option<PhysicalPosition<f64>> = None;
+    let mut shift_down = false;
+    let mut ctrl_down = false;
+    let mut alt_down = false;
+    let mut left_down = false;
+    
+    let mut render_state = block_on(render::State::new(&window));
+
+    event_loop.run(move |event, _, control_flow| match event {
+        Event::WindowEvent {
+            ref event,
+            window_id,
+        } if window_id == window.id() && !render_state.input(event) => {
+            match event {
+                WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
+
+                WindowEvent::KeyboardInput { input, .. } => match input {
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::Escape),
+                        ..
+                    } => *control_flow = ControlFlow::Exit,
+                    KeyboardInput { 
+                        state: ElementState::Pressed, 
+                        virtual_keycode: Some(VirtualKeyCode::Space),
+                        ..
+                    } => running = !running,
+                    KeyboardInput { 
+                        state: ElementState::Pressed, 
+                        virtual_keycode: Some(VirtualKeyCode::F5),
+                        ..
+                    } => if let Source::Live(simulation) = &source {
+                        match simulation.save(CHECKPOINT_PATH) {
+                            Ok(()) => log::info!("saved checkpoint to {}", CHECKPOINT_PATH),
+                            Err(e) => log::error!("{:?}", e),
+                        }
+                    },
+                    KeyboardInput { 
+                        state, 
+                        virtual_keycode: Some(VirtualKeyCode::LShift),
+                        ..
+                    } => shift_down = *state == ElementState::Pressed,
+                    KeyboardInput { 
+                        state, 
+                        virtual_keycode: Some(VirtualKeyCode::LControl),
+                        ..
+                    } => ctrl_down = *state == ElementState::Pressed,
+                    KeyboardInput {
+                        state,
+                        virtual_keycode: Some(VirtualKeyCode::LAlt),
+                        ..
+                    } => alt_down = *state == ElementState::Pressed,
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(key),
+                        ..
+                    } => if let Source::Replay(replay) = &mut source {
+                        match key {
+                            VirtualKeyCode::Right => {
+                                running = false;
+                                replay.step(1);
+                            }
+                            VirtualKeyCode::Left => {
+                                running = false;
+                                replay.step(-1);
+                            }
+                            VirtualKeyCode::Up => replay.set_speed(replay.speed() * 2.0),
+                            VirtualKeyCode::Down => replay.set_speed(replay.speed() / 2.0),
+                            VirtualKeyCode::R => replay.set_speed(-replay.speed()),
+                            VirtualKeyCode::Home => replay.seek(0.0),
+                            VirtualKeyCode::End => replay.seek_end(),
+                            _ => {}
+                        }
+                    },
+                    _ => {}
+                }
+
+                WindowEvent::CursorMoved { position, .. } => {
+                    last_cursor_position = Some(*position);
+
+                    if let (true, Source::Replay(replay)) = (left_down, &mut source) {
+                        replay.seek(position.x / window.inner_size().width as f64);
+                    }
+                }
+
+                WindowEvent::CursorLeft { .. } => {
+                    last_cursor_position = None;
+                }
+
+                WindowEvent::MouseInput {
+                    state,
+                    button: MouseButton::Left,
+                    ..
+                } => {
+                    left_down = *state == ElementState::Pressed;
+
+                    match (&mut source, last_cursor_position) {
+                        // dragging across the window scrubs through the recording
+                        (Source::Replay(replay), Some(position)) if left_down => {
+                            replay.seek(position.x / window.inner_size().width as f64);
+                        }
+                        (Source::Live(simulation), Some(position)) if !left_down => {
+                            let view_position = window_to_view_space(window.inner_size(), position);
+                            let barycentric_position = render_state.view_to_world_space(view_position);
+
+                            let mass = if ctrl_down { 
+                                simulation::BodyMass::Large 
+                            } else if shift_down {
+                                simulation::BodyMass::Medium
+                            } else {
+                                simulation::BodyMass::Small
+                            };
+
+                            let direction = if alt_down {
+                                simulation::OrbitDirection::Retrograde
+                            } else {
+                                simulation::OrbitDirection::Prograde
+                            };
+
+                            simulation.add_body_at_position(barycentric_position, mass, direction);
+                        }
+                        _ => {}
+                    }
+                }
+
+                WindowEvent::Resized(physical_size) => {
+                    render_state.resize(Some(*physical_size));
+                }
+
+                WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                    render_state.resize(Some(**new_inner_size));
+                }
+                _ => {}
+            } 
+        }
+
+        Event::RedrawRequested(_) => {
+            let (barycenter, instances, particles) = match &mut source {
+                Source::Live(simulation) => {
+                    if running {
+                        simulation.tick();
+
+                        for merge in simulation.take_merge_events() {
+                            log::info!(
+                                "t = {:.1}: {:?} (mass {}) absorbed {:?} (mass {}) at {:?}",
+                                merge.time,
+                                merge.survivor,
+                                merge.masses.0,
+                                merge.absorbed,
+                                merge.masses.1,
+                                merge.merged.position,
+                            );
+                        }
+
+                        if let Some(diagnostics) = simulation.diagnostics_every(DIAGNOSTICS_INTERVAL) {
+                            log::info!(
+                                "t = {:.1}: E = {:e} (drift {:e}), P drift {:e}, L drift {:e}",
+                                diagnostics.time,
+                                diagnostics.total_energy(),
+                                diagnostics.energy_drift,
+                                diagnostics.linear_momentum_drift,
+                                diagnostics.angular_momentum_drift,
+                            );
+                        }
+                    }
+
+                    (simulation.barycenter(), simulation.instances(), simulation.particle_instances())
+                }
+                Source::Replay(replay) => {
+                    if running {
+                        replay.advance();
+                    }
+
+                    let frame = replay.frame();
+                    (frame.barycenter(), frame.instances(), Vec::new())
+                }
+            };
+
+            render_state.update_light((barycenter.x as f32, barycenter.y as f32, barycenter.z as f32).into());
+            render_state.update_camera((barycenter.x as f32, barycenter.y as f32, barycenter.z as f32).into());
+            render_state.update_instances(instances);
+            render_state.update_particles(particles);
+
+            match render_state.render() {
+                Ok(_) => {}
+                Err(wgpu::SwapChainError::Lost) => render_state.resize(None),
+                Err(wgpu::SwapChainError::OutOfMemory) => *control_flow = ControlFlow::Exit,
+                Err(e) => eprintln!("{:?}", e),
+            }
+        }
+
+        Event::MainEventsCleared => {
+            window.request_redraw()
+        }
+
+        _ => {}
+    });
+}